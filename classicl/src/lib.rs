@@ -16,6 +16,7 @@
 
 use anyhow::Result;
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use tokio::sync::{mpsc::*, oneshot};
 
@@ -24,6 +25,7 @@ pub use classicl_packet::{client, server, Packet};
 pub use classicl_serde::{from_bytes, to_bytes};
 
 use log::{debug, error, info, trace};
+use serde::Deserialize;
 pub use serde::Serialize;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
@@ -33,7 +35,12 @@ use tokio::sync::mpsc;
 use tokio::sync::Mutex;
 use tokio_util::sync::CancellationToken;
 
+pub mod stats;
+use stats::STATS;
+
 type OnServerFull = Arc<Mutex<Box<dyn FnMut() -> server::DisconnectPlayer>>>;
+type OnClientAccept =
+    Arc<Mutex<Box<dyn FnMut(SocketAddr) -> Option<server::DisconnectPlayer> + Send>>>;
 type InitialHandleReceiver<T> = oneshot::Receiver<SignalHandle<T>>;
 type InitialHandleSender<T> = oneshot::Sender<SignalHandle<T>>;
 type InitialHandle<T> = (
@@ -51,7 +58,12 @@ pub struct Server {
     on_set_block: InitialHandle<OnSetBlock>,
     on_position_orientation: InitialHandle<OnPositionOrientation>,
     on_message: InitialHandle<OnMessage>,
+    on_ext_info: InitialHandle<OnExtInfo>,
+    on_ext_entry: InitialHandle<OnExtEntry>,
+    on_player_click: InitialHandle<OnPlayerClick>,
+    on_two_way_ping: InitialHandle<OnTwoWayPing>,
     on_server_full: OnServerFull,
+    on_client_accept: OnClientAccept,
 }
 
 impl Server {
@@ -89,9 +101,14 @@ impl Server {
             on_set_block: generate_initial_handle(oneshot::channel()),
             on_position_orientation: generate_initial_handle(oneshot::channel()),
             on_message: generate_initial_handle(oneshot::channel()),
+            on_ext_info: generate_initial_handle(oneshot::channel()),
+            on_ext_entry: generate_initial_handle(oneshot::channel()),
+            on_player_click: generate_initial_handle(oneshot::channel()),
+            on_two_way_ping: generate_initial_handle(oneshot::channel()),
             on_server_full: Arc::new(Mutex::new(Box::new(|| server::DisconnectPlayer {
                 disconnect_reason: "".into(),
             }))),
+            on_client_accept: Arc::new(Mutex::new(Box::new(|_| None))),
         })
     }
 
@@ -138,6 +155,29 @@ impl Server {
         let (handle, on_message) = SignalHandle::new();
         self.on_message.0.take().unwrap().send(handle).unwrap();
 
+        let (handle, on_ext_info) = SignalHandle::new();
+        self.on_ext_info.0.take().unwrap().send(handle).unwrap();
+
+        let (handle, on_ext_entry) = SignalHandle::new();
+        self.on_ext_entry.0.take().unwrap().send(handle).unwrap();
+
+        let (handle, on_player_click) = SignalHandle::new();
+        self.on_player_click.0.take().unwrap().send(handle).unwrap();
+
+        let (handle, on_two_way_ping) = SignalHandle::new();
+        self.on_two_way_ping.0.take().unwrap().send(handle).unwrap();
+
+        let signals = Signals {
+            on_player_identification,
+            on_set_block,
+            on_position_orientation,
+            on_message,
+            on_ext_info,
+            on_ext_entry,
+            on_player_click,
+            on_two_way_ping,
+        };
+
         debug!("Starting server loop.");
         loop {
             let (socket, s) = {
@@ -152,6 +192,15 @@ impl Server {
                 }
             };
 
+            if let Some(reason) = (self.on_client_accept.lock().await)(s) {
+                info!("{} was refused", s);
+                STATS.refuse();
+                let (_, mut write) = socket.into_split();
+                let _ = write.write_all(&[server::DisconnectPlayer::ID]).await;
+                let _ = write.write_all(&to_bytes(reason).unwrap()).await;
+                continue;
+            }
+
             let id = if let Some(i) = self.id_stack.lock().await.pop() {
                 i
             } else if self.current_id < i8::MAX
@@ -164,6 +213,7 @@ impl Server {
                 self.current_id += 1;
                 self.current_id
             } else {
+                STATS.refuse();
                 let (_, mut write) = socket.into_split();
                 write
                     .write_all(&[server::DisconnectPlayer::ID])
@@ -176,12 +226,15 @@ impl Server {
                 continue;
             };
             info!("{} connected with id {}", s, id);
+            STATS.connect();
             let (send, recv) = mpsc::channel(16);
             let disconnect = CancellationToken::new();
+            let extensions = Arc::new(Extensions::default());
 
             let ctrl = ClientController {
                 sender: send,
                 disconnect: disconnect.clone(),
+                extensions: extensions.clone(),
             };
             on_client_connected
                 .send(OnClientConnected {
@@ -195,24 +248,12 @@ impl Server {
             let id_stack = self.id_stack.clone();
 
             let on_client_disconnected = on_client_disconnected.clone();
-            let on_player_identification = on_player_identification.clone();
-            let on_set_block = on_set_block.clone();
-            let on_position_orientation = on_position_orientation.clone();
-            let on_client_message = on_message.clone();
+            let signals = signals.clone();
             tokio::spawn(async move {
-                if Self::client_loop(
-                    socket,
-                    recv,
-                    id,
-                    on_player_identification,
-                    on_set_block,
-                    on_position_orientation,
-                    on_client_message,
-                    disconnect,
-                )
-                .await
-                .is_err()
-                {
+                let result =
+                    Self::client_loop(socket, recv, id, signals, disconnect, extensions).await;
+                STATS.disconnect();
+                if result.is_err() {
                     on_client_disconnected
                         .send(OnClientDisconnected { id })
                         .await
@@ -228,11 +269,9 @@ impl Server {
         socket: TcpStream,
         mut recv: Receiver<Vec<u8>>,
         id: i8,
-        on_player_identification: Sender<OnPlayerIdentification>,
-        on_set_block: Sender<OnSetBlock>,
-        on_position_orientation: Sender<OnPositionOrientation>,
-        on_message: Sender<OnMessage>,
+        signals: Signals,
         disconnect: CancellationToken,
+        extensions: Arc<Extensions>,
     ) -> Result<()> {
         trace!("{id}'s client loop started.");
         let (mut reader, mut writer) = socket.into_split();
@@ -242,6 +281,7 @@ impl Server {
             let task = async {
                 while let Some(p) = recv.recv().await {
                     writer.write_all(&p).await?;
+                    STATS.send_bytes(p.len());
                     trace!("writing some bytes to {id}");
                 }
                 Ok(())
@@ -266,6 +306,7 @@ impl Server {
                 loop {
                     let mut buf = [0u8];
                     reader.read_exact(&mut buf).await?;
+                    STATS.receive_packet(buf[0]);
                     match buf[0] {
                         PlayerIdentification::ID => {
                             trace!("PlayerIdentification received from {id}");
@@ -273,25 +314,65 @@ impl Server {
                             reader.read_exact(&mut buf).await?;
                             let data: PlayerIdentification = from_bytes(&buf)?;
 
-                            on_player_identification
+                            signals
+                                .on_player_identification
                                 .send(OnPlayerIdentification { id, data })
                                 .await?;
                         }
+                        client::SetBlock::ID
+                            if extensions.extended_blocks.load(Ordering::Relaxed) =>
+                        {
+                            trace!("ExtSetBlock received from {id}");
+                            let mut buf = [0u8; client::ExtSetBlock::SIZE];
+                            reader.read_exact(&mut buf).await?;
+                            let data: client::ExtSetBlock = from_bytes(&buf)?;
+
+                            signals.on_set_block.send(OnSetBlock { id, data }).await?;
+                        }
                         client::SetBlock::ID => {
                             trace!("SetBlock received from {id}");
                             let mut buf = [0u8; client::SetBlock::SIZE];
                             reader.read_exact(&mut buf).await?;
                             let data: client::SetBlock = from_bytes(&buf)?;
 
-                            on_set_block.send(OnSetBlock { id, data }).await?;
+                            signals
+                                .on_set_block
+                                .send(OnSetBlock {
+                                    id,
+                                    data: data.into(),
+                                })
+                                .await?;
                         }
                         client::PositionOrientation::ID => {
                             trace!("PositionOrientation received from {id}");
-                            let mut buf = [0u8; client::PositionOrientation::SIZE];
+                            let extended_blocks =
+                                extensions.extended_blocks.load(Ordering::Relaxed);
+                            let ext_entity_positions =
+                                extensions.ext_entity_positions.load(Ordering::Relaxed);
+                            // The player id is followed by the location, both may be extended
+                            let id_size = if extended_blocks { 2 } else { 1 };
+                            let location_size = if ext_entity_positions {
+                                ExtLocation::SIZE
+                            } else {
+                                Location::SIZE
+                            };
+                            let mut buf = vec![0u8; id_size + location_size];
                             reader.read_exact(&mut buf).await?;
-                            let data: client::PositionOrientation = from_bytes(&buf)?;
-
-                            on_position_orientation
+                            let (player_id, location) = buf.split_at(id_size);
+                            let player_id = if extended_blocks {
+                                from_bytes(player_id)?
+                            } else {
+                                from_bytes::<u8>(player_id)?.into()
+                            };
+                            let location: ExtLocation = if ext_entity_positions {
+                                from_bytes(location)?
+                            } else {
+                                from_bytes::<Location>(location)?.into()
+                            };
+                            let data = location.with_player_id(player_id);
+
+                            signals
+                                .on_position_orientation
                                 .send(OnPositionOrientation { id, data })
                                 .await?;
                         }
@@ -301,7 +382,45 @@ impl Server {
                             reader.read_exact(&mut buf).await?;
                             let data: client::Message = from_bytes(&buf)?;
 
-                            on_message.send(OnMessage { id, data }).await?;
+                            signals.on_message.send(OnMessage { id, data }).await?;
+                        }
+                        client::ExtInfo::ID => {
+                            trace!("ExtInfo received from {id}");
+                            let mut buf = [0u8; client::ExtInfo::SIZE];
+                            reader.read_exact(&mut buf).await?;
+                            let data: client::ExtInfo = from_bytes(&buf)?;
+
+                            signals.on_ext_info.send(OnExtInfo { id, data }).await?;
+                        }
+                        client::ExtEntry::ID => {
+                            trace!("ExtEntry received from {id}");
+                            let mut buf = [0u8; client::ExtEntry::SIZE];
+                            reader.read_exact(&mut buf).await?;
+                            let data: client::ExtEntry = from_bytes(&buf)?;
+
+                            signals.on_ext_entry.send(OnExtEntry { id, data }).await?;
+                        }
+                        client::PlayerClick::ID => {
+                            trace!("PlayerClick received from {id}");
+                            let mut buf = [0u8; client::PlayerClick::SIZE];
+                            reader.read_exact(&mut buf).await?;
+                            let data: client::PlayerClick = from_bytes(&buf)?;
+
+                            signals
+                                .on_player_click
+                                .send(OnPlayerClick { id, data })
+                                .await?;
+                        }
+                        client::TwoWayPing::ID => {
+                            trace!("TwoWayPing received from {id}");
+                            let mut buf = [0u8; client::TwoWayPing::SIZE];
+                            reader.read_exact(&mut buf).await?;
+                            let data: client::TwoWayPing = from_bytes(&buf)?;
+
+                            signals
+                                .on_two_way_ping
+                                .send(OnTwoWayPing { id, data })
+                                .await?;
                         }
                         i => trace!("Unknown packet id ({i}) received from {id}"),
                     }
@@ -368,6 +487,26 @@ impl Server {
         self.on_message.1.take()
     }
 
+    /// Calls given function when a client (identified by id) wrote a [`client::ExtInfo`] Packet.
+    pub fn on_ext_info(&mut self) -> Option<InitialHandleReceiver<OnExtInfo>> {
+        self.on_ext_info.1.take()
+    }
+
+    /// Calls given function when a client (identified by id) wrote a [`client::ExtEntry`] Packet.
+    pub fn on_ext_entry(&mut self) -> Option<InitialHandleReceiver<OnExtEntry>> {
+        self.on_ext_entry.1.take()
+    }
+
+    /// Calls given function when a client (identified by id) wrote a [`client::PlayerClick`] Packet.
+    pub fn on_player_click(&mut self) -> Option<InitialHandleReceiver<OnPlayerClick>> {
+        self.on_player_click.1.take()
+    }
+
+    /// Calls given function when a client (identified by id) wrote a [`client::TwoWayPing`] Packet.
+    pub fn on_two_way_ping(&mut self) -> Option<InitialHandleReceiver<OnTwoWayPing>> {
+        self.on_two_way_ping.1.take()
+    }
+
     /// Calls given function when either the ids are exhausted (>= [`i8::MAX`]) or the given limit is reached.
     pub async fn on_server_full<F>(&mut self, f: F)
     where
@@ -375,6 +514,79 @@ impl Server {
     {
         *self.on_server_full.lock().await = Box::new(f);
     }
+
+    /// Calls given function for every new connection before it gets an id, the connection is
+    /// closed with the returned [`server::DisconnectPlayer`] if there is one.
+    pub async fn on_client_accept<F>(&mut self, f: F)
+    where
+        F: FnMut(SocketAddr) -> Option<server::DisconnectPlayer> + 'static + Send,
+    {
+        *self.on_client_accept.lock().await = Box::new(f);
+    }
+}
+
+#[derive(Clone)]
+struct Signals {
+    on_player_identification: Sender<OnPlayerIdentification>,
+    on_set_block: Sender<OnSetBlock>,
+    on_position_orientation: Sender<OnPositionOrientation>,
+    on_message: Sender<OnMessage>,
+    on_ext_info: Sender<OnExtInfo>,
+    on_ext_entry: Sender<OnExtEntry>,
+    on_player_click: Sender<OnPlayerClick>,
+    on_two_way_ping: Sender<OnTwoWayPing>,
+}
+
+/// Negotiated extensions changing the size of packets sent by a client
+#[derive(Debug, Default)]
+struct Extensions {
+    extended_blocks: AtomicBool,
+    ext_entity_positions: AtomicBool,
+}
+
+/// Everything of a [`client::PositionOrientation`] following the player id
+#[derive(Default, FixedSize, Serialize, Deserialize)]
+struct Location {
+    x: i16,
+    y: i16,
+    z: i16,
+    yaw: u8,
+    pitch: u8,
+}
+
+/// [`Location`] sent by clients supporting ExtEntityPositions
+#[derive(Default, FixedSize, Serialize, Deserialize)]
+struct ExtLocation {
+    x: i32,
+    y: i32,
+    z: i32,
+    yaw: u8,
+    pitch: u8,
+}
+
+impl From<Location> for ExtLocation {
+    fn from(l: Location) -> Self {
+        Self {
+            x: l.x.into(),
+            y: l.y.into(),
+            z: l.z.into(),
+            yaw: l.yaw,
+            pitch: l.pitch,
+        }
+    }
+}
+
+impl ExtLocation {
+    fn with_player_id(self, player_id: u16) -> ExtPositionOrientation {
+        ExtPositionOrientation {
+            player_id,
+            x: self.x,
+            y: self.y,
+            z: self.z,
+            yaw: self.yaw,
+            pitch: self.pitch,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -411,13 +623,13 @@ pub struct OnPlayerIdentification {
 #[derive(Debug)]
 pub struct OnSetBlock {
     pub id: i8,
-    pub data: SetBlock,
+    pub data: ExtSetBlock,
 }
 
 #[derive(Debug)]
 pub struct OnPositionOrientation {
     pub id: i8,
-    pub data: PositionOrientation,
+    pub data: ExtPositionOrientation,
 }
 
 #[derive(Debug)]
@@ -426,6 +638,30 @@ pub struct OnMessage {
     pub data: client::Message,
 }
 
+#[derive(Debug)]
+pub struct OnExtInfo {
+    pub id: i8,
+    pub data: client::ExtInfo,
+}
+
+#[derive(Debug)]
+pub struct OnExtEntry {
+    pub id: i8,
+    pub data: client::ExtEntry,
+}
+
+#[derive(Debug)]
+pub struct OnPlayerClick {
+    pub id: i8,
+    pub data: client::PlayerClick,
+}
+
+#[derive(Debug)]
+pub struct OnTwoWayPing {
+    pub id: i8,
+    pub data: client::TwoWayPing,
+}
+
 /// Controls the connection to a specific client.
 ///
 /// # Examples
@@ -473,6 +709,7 @@ pub struct OnMessage {
 pub struct ClientController {
     sender: mpsc::Sender<Vec<u8>>,
     disconnect: CancellationToken,
+    extensions: Arc<Extensions>,
 }
 
 impl ClientController {
@@ -486,19 +723,38 @@ impl ClientController {
     ) -> Result<(), Box<dyn std::error::Error>> {
         let mut buf = vec![T::ID];
         buf.append(&mut to_bytes(p)?);
+        STATS.send_packet(T::ID);
         let sender = self.sender.clone();
         trace!("Trying to send a packet with id {}", T::ID);
-        tokio::spawn(async move { sender.send(buf).await.unwrap() });
+        // The packet is dropped if the client disconnected in the meantime
+        tokio::spawn(async move { sender.send(buf).await.ok() });
         Ok(())
     }
 
     /// Writes bytes into the buffer.
-    pub async fn write_bytes(&self, b: Vec<u8>) {
-        let sender = self.sender.clone();
+    ///
+    /// Fails if the client has already disconnected.
+    pub async fn write_bytes(&self, b: Vec<u8>) -> Result<()> {
         trace!("Trying to send some bytes.");
-        tokio::spawn(async move { sender.send(b).await.unwrap() })
+        self.sender
+            .send(b)
             .await
-            .unwrap();
+            .map_err(|_| anyhow::anyhow!("the client has disconnected"))
+    }
+
+    /// Reads block ids sent by the client as two bytes, needed once ExtendedBlocks was negotiated.
+    pub fn enable_extended_blocks(&self) {
+        self.extensions
+            .extended_blocks
+            .store(true, Ordering::Relaxed);
+    }
+
+    /// Reads positions sent by the client as four bytes, needed once ExtEntityPositions was
+    /// negotiated.
+    pub fn enable_ext_entity_positions(&self) {
+        self.extensions
+            .ext_entity_positions
+            .store(true, Ordering::Relaxed);
     }
 
     /// Queues the client for disconnection. If reason given it will displayed when the player leaves the game.
@@ -510,6 +766,15 @@ impl ClientController {
     }
 }
 
+/// Appends a packet to `buf` so multiple packets can be written in order using
+/// [`ClientController::write_bytes`].
+pub fn push_packet<T: Serialize + Packet>(buf: &mut Vec<u8>, p: &T) -> Result<()> {
+    buf.push(T::ID);
+    buf.append(&mut to_bytes(p)?);
+    STATS.send_packet(T::ID);
+    Ok(())
+}
+
 fn generate_initial_handle<T>(
     channel: (
         oneshot::Sender<SignalHandle<T>>,
@@ -0,0 +1,109 @@
+/* This file is part of classicl.
+ *
+ * classicl is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Traffic of every server in this process
+pub static STATS: Stats = Stats::new();
+
+/// Counters of the traffic between servers and their clients
+pub struct Stats {
+    connections: AtomicU64,
+    disconnections: AtomicU64,
+    refused: AtomicU64,
+    packets_received: [AtomicU64; 256],
+    packets_sent: [AtomicU64; 256],
+    bytes_sent: AtomicU64,
+}
+
+impl Stats {
+    const fn new() -> Self {
+        Self {
+            connections: AtomicU64::new(0),
+            disconnections: AtomicU64::new(0),
+            refused: AtomicU64::new(0),
+            packets_received: [const { AtomicU64::new(0) }; 256],
+            packets_sent: [const { AtomicU64::new(0) }; 256],
+            bytes_sent: AtomicU64::new(0),
+        }
+    }
+
+    /// Clients which got an id since the start
+    pub fn connections(&self) -> u64 {
+        self.connections.load(Ordering::Relaxed)
+    }
+
+    /// Clients connected right now
+    pub fn connected(&self) -> u64 {
+        self.connections()
+            .saturating_sub(self.disconnections.load(Ordering::Relaxed))
+    }
+
+    /// Connections closed right away because of the accept hook or the player limit
+    pub fn refused(&self) -> u64 {
+        self.refused.load(Ordering::Relaxed)
+    }
+
+    /// Number of received packets by id, leaving out ids which were never received
+    pub fn packets_received(&self) -> impl Iterator<Item = (u8, u64)> + '_ {
+        count_by_id(&self.packets_received)
+    }
+
+    /// Number of sent packets by id, leaving out ids which were never sent
+    pub fn packets_sent(&self) -> impl Iterator<Item = (u8, u64)> + '_ {
+        count_by_id(&self.packets_sent)
+    }
+
+    pub fn bytes_sent(&self) -> u64 {
+        self.bytes_sent.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn connect(&self) {
+        self.connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn disconnect(&self) {
+        self.disconnections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn refuse(&self) {
+        self.refused.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn receive_packet(&self, id: u8) {
+        self.packets_received[usize::from(id)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn send_packet(&self, id: u8) {
+        self.packets_sent[usize::from(id)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts packets encoded without [`push_packet`](crate::push_packet), like BulkBlockUpdate.
+    pub fn send_packets(&self, id: u8, count: usize) {
+        self.packets_sent[usize::from(id)].fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn send_bytes(&self, count: usize) {
+        self.bytes_sent.fetch_add(count as u64, Ordering::Relaxed);
+    }
+}
+
+fn count_by_id(counters: &[AtomicU64; 256]) -> impl Iterator<Item = (u8, u64)> + '_ {
+    (0..=u8::MAX)
+        .zip(counters)
+        .map(|(id, count)| (id, count.load(Ordering::Relaxed)))
+        .filter(|(_, count)| *count > 0)
+}
@@ -45,6 +45,9 @@ pub fn fixed_size_derive(input: TokenStream) -> TokenStream {
                 "i8" => size += 1,
                 "u8" => size += 1,
                 "i16" => size += 2,
+                "u16" => size += 2,
+                "i32" => size += 4,
+                "f32" => size += 4,
                 "String" => size += 64,
                 _ => {
                     let mut buf = String::new();
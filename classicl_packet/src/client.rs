@@ -43,6 +43,32 @@ impl Packet for SetBlock {
     const ID: u8 = 0x05;
 }
 
+/// [`SetBlock`] sent by clients supporting ExtendedBlocks
+#[derive(Default, Debug, FixedSize, Serialize, Deserialize)]
+pub struct ExtSetBlock {
+    pub x: i16,
+    pub y: i16,
+    pub z: i16,
+    pub mode: u8,
+    pub block_type: u16,
+}
+
+impl Packet for ExtSetBlock {
+    const ID: u8 = 0x05;
+}
+
+impl From<SetBlock> for ExtSetBlock {
+    fn from(p: SetBlock) -> Self {
+        Self {
+            x: p.x,
+            y: p.y,
+            z: p.z,
+            mode: p.mode,
+            block_type: p.block_type.into(),
+        }
+    }
+}
+
 #[derive(Default, Debug, FixedSize, Serialize, Deserialize)]
 pub struct PositionOrientation {
     pub player_id: u8,
@@ -57,6 +83,22 @@ impl Packet for PositionOrientation {
     const ID: u8 = 0x08;
 }
 
+/// [`PositionOrientation`] sent by clients supporting both ExtendedBlocks and ExtEntityPositions
+#[derive(Default, Debug, FixedSize, Serialize, Deserialize)]
+pub struct ExtPositionOrientation {
+    /// The held block for clients supporting HeldBlock
+    pub player_id: u16,
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+    pub yaw: u8,
+    pub pitch: u8,
+}
+
+impl Packet for ExtPositionOrientation {
+    const ID: u8 = 0x08;
+}
+
 #[derive(Default, Debug, FixedSize, Serialize, Deserialize)]
 pub struct Message {
     pub unused: u8,
@@ -66,3 +108,50 @@ pub struct Message {
 impl Packet for Message {
     const ID: u8 = 0x0d;
 }
+
+#[derive(Default, Debug, FixedSize, Serialize, Deserialize)]
+pub struct ExtInfo {
+    pub app_name: String,
+    pub extension_count: i16,
+}
+
+impl Packet for ExtInfo {
+    const ID: u8 = 0x10;
+}
+
+#[derive(Default, Debug, FixedSize, Serialize, Deserialize)]
+pub struct ExtEntry {
+    pub ext_name: String,
+    pub version: i32,
+}
+
+impl Packet for ExtEntry {
+    const ID: u8 = 0x11;
+}
+
+#[derive(Default, Debug, FixedSize, Serialize, Deserialize)]
+pub struct PlayerClick {
+    pub button: u8,
+    pub action: u8,
+    pub yaw: i16,
+    pub pitch: i16,
+    pub target_entity_id: i8,
+    pub target_block_x: i16,
+    pub target_block_y: i16,
+    pub target_block_z: i16,
+    pub target_block_face: u8,
+}
+
+impl Packet for PlayerClick {
+    const ID: u8 = 0x22;
+}
+
+#[derive(Default, Debug, FixedSize, Serialize, Deserialize)]
+pub struct TwoWayPing {
+    pub direction: u8,
+    pub data: u16,
+}
+
+impl Packet for TwoWayPing {
+    const ID: u8 = 0x2b;
+}
@@ -45,6 +45,16 @@ impl Packet for LevelInitialize {
     const ID: u8 = 0x02;
 }
 
+/// [`LevelInitialize`] sent to clients supporting FastMap
+#[derive(Default, Debug, FixedSize, Serialize, Deserialize)]
+pub struct ExtLevelInitialize {
+    pub map_size: i32,
+}
+
+impl Packet for ExtLevelInitialize {
+    const ID: u8 = 0x02;
+}
+
 #[serde_as]
 #[derive(Default, Debug, FixedSize, Serialize, Deserialize)]
 pub struct LevelDataChunk {
@@ -81,6 +91,19 @@ impl Packet for SetBlock {
     const ID: u8 = 0x06;
 }
 
+/// [`SetBlock`] sent to clients supporting ExtendedBlocks
+#[derive(Default, Debug, FixedSize, Serialize, Deserialize)]
+pub struct ExtSetBlock {
+    pub x: i16,
+    pub y: i16,
+    pub z: i16,
+    pub block_type: u16,
+}
+
+impl Packet for ExtSetBlock {
+    const ID: u8 = 0x06;
+}
+
 #[derive(Default, Debug, FixedSize, Serialize, Deserialize)]
 pub struct SpawnPlayer {
     pub player_id: i8,
@@ -96,6 +119,22 @@ impl Packet for SpawnPlayer {
     const ID: u8 = 0x07;
 }
 
+/// [`SpawnPlayer`] sent to clients supporting ExtEntityPositions
+#[derive(Default, Debug, FixedSize, Serialize, Deserialize)]
+pub struct ExtSpawnPlayer {
+    pub player_id: i8,
+    pub player_name: String,
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+    pub yaw: u8,
+    pub pitch: u8,
+}
+
+impl Packet for ExtSpawnPlayer {
+    const ID: u8 = 0x07;
+}
+
 #[derive(Default, Debug, FixedSize, Serialize, Deserialize)]
 pub struct PositionOrientationTeleport {
     pub player_id: i8,
@@ -110,6 +149,21 @@ impl Packet for PositionOrientationTeleport {
     const ID: u8 = 0x08;
 }
 
+/// [`PositionOrientationTeleport`] sent to clients supporting ExtEntityPositions
+#[derive(Default, Debug, FixedSize, Serialize, Deserialize)]
+pub struct ExtPositionOrientationTeleport {
+    pub player_id: i8,
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+    pub yaw: u8,
+    pub pitch: u8,
+}
+
+impl Packet for ExtPositionOrientationTeleport {
+    const ID: u8 = 0x08;
+}
+
 #[derive(Default, Debug, FixedSize, Serialize, Deserialize)]
 pub struct PositionOrientationUpdate {
     pub player_id: i8,
@@ -183,3 +237,405 @@ pub struct UpdateUserType {
 impl Packet for UpdateUserType {
     const ID: u8 = 0x0f;
 }
+
+#[derive(Default, Debug, FixedSize, Serialize, Deserialize)]
+pub struct ExtInfo {
+    pub app_name: String,
+    pub extension_count: i16,
+}
+
+impl Packet for ExtInfo {
+    const ID: u8 = 0x10;
+}
+
+#[derive(Default, Debug, FixedSize, Serialize, Deserialize)]
+pub struct ExtEntry {
+    pub ext_name: String,
+    pub version: i32,
+}
+
+impl Packet for ExtEntry {
+    const ID: u8 = 0x11;
+}
+
+#[derive(Default, Debug, FixedSize, Serialize, Deserialize)]
+pub struct HackControl {
+    pub flying: u8,
+    pub no_clip: u8,
+    pub speeding: u8,
+    pub spawn_control: u8,
+    pub third_person_view: u8,
+    pub jump_height: i16,
+}
+
+impl Packet for HackControl {
+    const ID: u8 = 0x20;
+}
+
+#[derive(Default, Debug, FixedSize, Serialize, Deserialize)]
+pub struct MakeSelection {
+    pub selection_id: u8,
+    pub label: String,
+    pub start_x: i16,
+    pub start_y: i16,
+    pub start_z: i16,
+    pub end_x: i16,
+    pub end_y: i16,
+    pub end_z: i16,
+    pub red: i16,
+    pub green: i16,
+    pub blue: i16,
+    pub opacity: i16,
+}
+
+impl Packet for MakeSelection {
+    const ID: u8 = 0x1a;
+}
+
+#[derive(Default, Debug, FixedSize, Serialize, Deserialize)]
+pub struct RemoveSelection {
+    pub selection_id: u8,
+}
+
+impl Packet for RemoveSelection {
+    const ID: u8 = 0x1b;
+}
+
+#[derive(Default, Debug, FixedSize, Serialize, Deserialize)]
+pub struct HoldThis {
+    pub block_to_hold: u8,
+    pub prevent_change: u8,
+}
+
+impl Packet for HoldThis {
+    const ID: u8 = 0x14;
+}
+
+/// [`HoldThis`] sent to clients supporting ExtendedBlocks
+#[derive(Default, Debug, FixedSize, Serialize, Deserialize)]
+pub struct ExtHoldThis {
+    pub block_to_hold: u16,
+    pub prevent_change: u8,
+}
+
+impl Packet for ExtHoldThis {
+    const ID: u8 = 0x14;
+}
+
+#[derive(Default, Debug, FixedSize, Serialize, Deserialize)]
+pub struct ChangeModel {
+    pub entity_id: i8,
+    pub model: String,
+}
+
+impl Packet for ChangeModel {
+    const ID: u8 = 0x1d;
+}
+
+#[derive(Default, Debug, FixedSize, Serialize, Deserialize)]
+pub struct EntityProperty {
+    pub entity_id: i8,
+    pub property_type: u8,
+    pub property_value: i32,
+}
+
+impl Packet for EntityProperty {
+    const ID: u8 = 0x2a;
+}
+
+#[derive(Default, Debug, FixedSize, Serialize, Deserialize)]
+pub struct TwoWayPing {
+    pub direction: u8,
+    pub data: u16,
+}
+
+impl Packet for TwoWayPing {
+    const ID: u8 = 0x2b;
+}
+
+#[derive(Default, Debug, FixedSize, Serialize, Deserialize)]
+pub struct ExtAddPlayerName {
+    pub name_id: i16,
+    pub player_name: String,
+    pub list_name: String,
+    pub group_name: String,
+    pub group_rank: u8,
+}
+
+impl Packet for ExtAddPlayerName {
+    const ID: u8 = 0x16;
+}
+
+#[derive(Default, Debug, FixedSize, Serialize, Deserialize)]
+pub struct ExtRemovePlayerName {
+    pub name_id: i16,
+}
+
+impl Packet for ExtRemovePlayerName {
+    const ID: u8 = 0x18;
+}
+
+#[derive(Default, Debug, FixedSize, Serialize, Deserialize)]
+pub struct SetHotbar {
+    pub block_id: u8,
+    pub hotbar_index: u8,
+}
+
+impl Packet for SetHotbar {
+    const ID: u8 = 0x2d;
+}
+
+/// [`SetHotbar`] sent to clients supporting ExtendedBlocks
+#[derive(Default, Debug, FixedSize, Serialize, Deserialize)]
+pub struct ExtSetHotbar {
+    pub block_id: u16,
+    pub hotbar_index: u8,
+}
+
+impl Packet for ExtSetHotbar {
+    const ID: u8 = 0x2d;
+}
+
+#[derive(Default, Debug, FixedSize, Serialize, Deserialize)]
+pub struct SetSpawnpoint {
+    pub x: i16,
+    pub y: i16,
+    pub z: i16,
+    pub yaw: u8,
+    pub pitch: u8,
+}
+
+impl Packet for SetSpawnpoint {
+    const ID: u8 = 0x2e;
+}
+
+/// [`SetSpawnpoint`] sent to clients supporting ExtEntityPositions
+#[derive(Default, Debug, FixedSize, Serialize, Deserialize)]
+pub struct ExtSetSpawnpoint {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+    pub yaw: u8,
+    pub pitch: u8,
+}
+
+impl Packet for ExtSetSpawnpoint {
+    const ID: u8 = 0x2e;
+}
+
+#[derive(Default, Debug, FixedSize, Serialize, Deserialize)]
+pub struct VelocityControl {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+    pub x_mode: u8,
+    pub y_mode: u8,
+    pub z_mode: u8,
+}
+
+impl Packet for VelocityControl {
+    const ID: u8 = 0x2f;
+}
+
+#[derive(Default, Debug, FixedSize, Serialize, Deserialize)]
+pub struct SetTextHotKey {
+    pub label: String,
+    pub action: String,
+    pub key_code: i32,
+    pub key_mods: u8,
+}
+
+impl Packet for SetTextHotKey {
+    const ID: u8 = 0x15;
+}
+
+#[derive(Default, Debug, FixedSize, Serialize, Deserialize)]
+pub struct CinematicGui {
+    pub hide_crosshair: u8,
+    pub hide_hand: u8,
+    pub hide_hotbar: u8,
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+    pub opacity: u8,
+    pub bar_size: u16,
+}
+
+impl Packet for CinematicGui {
+    const ID: u8 = 0x38;
+}
+
+#[derive(Default, Debug, FixedSize, Serialize, Deserialize)]
+pub struct DefineEffect {
+    pub effect_id: u8,
+    pub u1: u8,
+    pub v1: u8,
+    pub u2: u8,
+    pub v2: u8,
+    pub red_tint: u8,
+    pub green_tint: u8,
+    pub blue_tint: u8,
+    pub frame_count: u8,
+    pub particle_count: u8,
+    pub size: u8,
+    pub size_variation: i32,
+    pub spread: u16,
+    pub speed: i32,
+    pub gravity: i32,
+    pub base_lifetime: i32,
+    pub lifetime_variation: i32,
+    pub collide_flags: u8,
+    pub full_bright: u8,
+}
+
+impl Packet for DefineEffect {
+    const ID: u8 = 0x30;
+}
+
+#[derive(Default, Debug, FixedSize, Serialize, Deserialize)]
+pub struct SpawnEffect {
+    pub effect_id: u8,
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+    pub origin_x: i32,
+    pub origin_y: i32,
+    pub origin_z: i32,
+}
+
+impl Packet for SpawnEffect {
+    const ID: u8 = 0x31;
+}
+
+#[derive(Default, Debug, FixedSize, Serialize, Deserialize)]
+pub struct DefineModel {
+    pub model_id: u8,
+    pub name: String,
+    pub flags: u8,
+    pub name_y: f32,
+    pub eye_y: f32,
+    pub collision_x: f32,
+    pub collision_y: f32,
+    pub collision_z: f32,
+    pub picking_min_x: f32,
+    pub picking_min_y: f32,
+    pub picking_min_z: f32,
+    pub picking_max_x: f32,
+    pub picking_max_y: f32,
+    pub picking_max_z: f32,
+    pub u_scale: u16,
+    pub v_scale: u16,
+    pub parts_count: u8,
+}
+
+impl Packet for DefineModel {
+    const ID: u8 = 0x32;
+}
+
+/// Box of a [`DefineModel`], sent as often as its `parts_count`
+#[derive(Default, Debug, FixedSize, Serialize, Deserialize)]
+pub struct DefineModelPart {
+    pub model_id: u8,
+    pub min_x: f32,
+    pub min_y: f32,
+    pub min_z: f32,
+    pub max_x: f32,
+    pub max_y: f32,
+    pub max_z: f32,
+    pub top_u1: u16,
+    pub top_v1: u16,
+    pub top_u2: u16,
+    pub top_v2: u16,
+    pub bottom_u1: u16,
+    pub bottom_v1: u16,
+    pub bottom_u2: u16,
+    pub bottom_v2: u16,
+    pub front_u1: u16,
+    pub front_v1: u16,
+    pub front_u2: u16,
+    pub front_v2: u16,
+    pub back_u1: u16,
+    pub back_v1: u16,
+    pub back_u2: u16,
+    pub back_v2: u16,
+    pub left_u1: u16,
+    pub left_v1: u16,
+    pub left_u2: u16,
+    pub left_v2: u16,
+    pub right_u1: u16,
+    pub right_v1: u16,
+    pub right_u2: u16,
+    pub right_v2: u16,
+    pub rotation_origin_x: f32,
+    pub rotation_origin_y: f32,
+    pub rotation_origin_z: f32,
+    pub rotation_x: f32,
+    pub rotation_y: f32,
+    pub rotation_z: f32,
+    pub anim1_flags: u8,
+    pub anim1_a: f32,
+    pub anim1_b: f32,
+    pub anim1_c: f32,
+    pub anim1_d: f32,
+    pub anim2_flags: u8,
+    pub anim2_a: f32,
+    pub anim2_b: f32,
+    pub anim2_c: f32,
+    pub anim2_d: f32,
+    pub anim3_flags: u8,
+    pub anim3_a: f32,
+    pub anim3_b: f32,
+    pub anim3_c: f32,
+    pub anim3_d: f32,
+    pub anim4_flags: u8,
+    pub anim4_a: f32,
+    pub anim4_b: f32,
+    pub anim4_c: f32,
+    pub anim4_d: f32,
+    pub flags: u8,
+}
+
+impl Packet for DefineModelPart {
+    const ID: u8 = 0x33;
+}
+
+/// Up to [`BulkBlockUpdate::MAX_BLOCKS`] block changes at once
+///
+/// Its arrays do not fit the fixed-size serializer, so it is written with
+/// [`BulkBlockUpdate::to_bytes`] instead of being a [`Packet`].
+#[derive(Default, Debug)]
+pub struct BulkBlockUpdate {
+    /// Indices into the level's block array
+    pub indices: Vec<i32>,
+    pub blocks: Vec<u16>,
+}
+
+impl BulkBlockUpdate {
+    pub const ID: u8 = 0x26;
+    pub const MAX_BLOCKS: usize = 256;
+
+    /// Encodes the packet including its id. With ExtendedBlocks the upper two bits of every
+    /// block id are appended.
+    pub fn to_bytes(&self, extended_blocks: bool) -> Vec<u8> {
+        let count = self.indices.len().min(Self::MAX_BLOCKS);
+        let mut buf = Vec::with_capacity(1 + 1 + 4 * Self::MAX_BLOCKS + Self::MAX_BLOCKS + 64);
+        buf.push(Self::ID);
+        buf.push(count.saturating_sub(1) as u8);
+        for i in 0..Self::MAX_BLOCKS {
+            let index = self.indices.get(i).filter(|_| i < count).copied();
+            buf.extend_from_slice(&index.unwrap_or(0).to_be_bytes());
+        }
+        for i in 0..Self::MAX_BLOCKS {
+            let block = self.blocks.get(i).filter(|_| i < count).copied();
+            buf.push(block.unwrap_or(0) as u8);
+        }
+        if extended_blocks {
+            let mut upper = [0u8; Self::MAX_BLOCKS / 4];
+            for (i, block) in self.blocks.iter().take(count).enumerate() {
+                upper[i / 4] |= ((block >> 8) as u8 & 0b11) << ((i % 4) * 2);
+            }
+            buf.extend_from_slice(&upper);
+        }
+        buf
+    }
+}
@@ -62,6 +62,36 @@ impl<'de> Deserializer<'de> {
         }
     }
 
+    fn parse_u16(&mut self) -> Result<u16, Error> {
+        if let Some(a) = self.input.get(0..2) {
+            if self.input.len() > 2 {
+                self.input = &self.input[2..];
+            } else {
+                self.input = &[]
+            }
+            let mut buf = [0u8; 2];
+            buf.copy_from_slice(a);
+            Ok(u16::from_be_bytes(buf))
+        } else {
+            Err(Error::WrongPacket)
+        }
+    }
+
+    fn parse_i32(&mut self) -> Result<i32, Error> {
+        if let Some(a) = self.input.get(0..4) {
+            if self.input.len() > 4 {
+                self.input = &self.input[4..];
+            } else {
+                self.input = &[]
+            }
+            let mut buf = [0u8; 4];
+            buf.copy_from_slice(a);
+            Ok(i32::from_be_bytes(buf))
+        } else {
+            Err(Error::WrongPacket)
+        }
+    }
+
     fn parse_bytes(&mut self) -> Result<[u8; 1024], Error> {
         if let Some(a) = self.input.get(0..1024) {
             self.input = &self.input[1024..]; // Byte array never the last bytes in packet
@@ -122,11 +152,11 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         visitor.visit_i16(self.parse_i16()?)
     }
 
-    fn deserialize_i32<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
+    fn deserialize_i32<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        Err(Error::NotSupported)
+        visitor.visit_i32(self.parse_i32()?)
     }
 
     fn deserialize_i64<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
@@ -143,11 +173,11 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         visitor.visit_u8(self.parse_u8()?)
     }
 
-    fn deserialize_u16<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
+    fn deserialize_u16<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        Err(Error::NotSupported)
+        visitor.visit_u16(self.parse_u16()?)
     }
 
     fn deserialize_u32<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
@@ -164,11 +194,11 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         Err(Error::NotSupported)
     }
 
-    fn deserialize_f32<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
+    fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        Err(Error::NotSupported)
+        visitor.visit_f32(f32::from_bits(self.parse_i32()? as u32))
     }
 
     fn deserialize_f64<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
@@ -53,8 +53,9 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         Ok(())
     }
 
-    fn serialize_i32(self, _v: i32) -> Result<()> {
-        Err(Error::NotSupported)
+    fn serialize_i32(self, v: i32) -> Result<()> {
+        self.output.append(&mut Vec::from(v.to_be_bytes()));
+        Ok(())
     }
 
     fn serialize_i64(self, _v: i64) -> Result<()> {
@@ -66,8 +67,9 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         Ok(())
     }
 
-    fn serialize_u16(self, _v: u16) -> Result<()> {
-        Err(Error::NotSupported)
+    fn serialize_u16(self, v: u16) -> Result<()> {
+        self.output.append(&mut Vec::from(v.to_be_bytes()));
+        Ok(())
     }
 
     fn serialize_u32(self, _v: u32) -> Result<()> {
@@ -78,8 +80,9 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         Err(Error::NotSupported)
     }
 
-    fn serialize_f32(self, _v: f32) -> Result<()> {
-        Err(Error::NotSupported)
+    fn serialize_f32(self, v: f32) -> Result<()> {
+        self.output.append(&mut Vec::from(v.to_be_bytes()));
+        Ok(())
     }
 
     fn serialize_f64(self, _v: f64) -> Result<()> {
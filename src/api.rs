@@ -0,0 +1,357 @@
+/* This file is part of classicl-server.
+ *
+ * classicl-server is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    convert::Infallible,
+    future::Future,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use hyper::{
+    header,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use log::{info, warn};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    commands::Command, console, heartbeat, login, metrics, ranks::Rank, run_command, secret,
+    Sender, State, PLAYER_HEIGHT,
+};
+
+/// Largest request body accepted
+const MAX_BODY_SIZE: u64 = 16 * 1024;
+
+/// Location of the token needed for POST requests inside the data directory
+pub fn token_path(data: &Path) -> PathBuf {
+    data.join("api_token")
+}
+
+/// Serves the JSON API and the Prometheus metrics at `/metrics` on `address`.
+///
+/// GET requests are open to everyone, POST requests need an `Authorization: Bearer <token>`
+/// header.
+pub async fn run(address: String, token: String, state: State) {
+    let token = Arc::new(token);
+    serve(&address, "the HTTP API", move |req| {
+        let state = state.clone();
+        let token = token.clone();
+        async move { handle(req, &token, &state).await }
+    })
+    .await;
+}
+
+/// Serves only the Prometheus metrics at `/metrics` on `address`, without needing the API.
+pub async fn run_metrics(address: String, state: State) {
+    serve(&address, "the metrics", move |req| {
+        let state = state.clone();
+        async move {
+            let path = req.uri().path().trim_end_matches('/');
+            match (req.method(), path) {
+                (&Method::GET, "/metrics") => metrics_response(&state).await,
+                (_, "/metrics") => error(StatusCode::METHOD_NOT_ALLOWED, "Method not allowed"),
+                _ => error(StatusCode::NOT_FOUND, "Not found"),
+            }
+        }
+    })
+    .await;
+}
+
+/// Answers every request on `address` with `handle`, `what` naming the service in the log.
+async fn serve<F, R>(address: &str, what: &str, handle: F)
+where
+    F: Fn(Request<Body>) -> R + Clone + Send + Sync + 'static,
+    R: Future<Output = Response<Body>> + Send + 'static,
+{
+    let addr = match address.parse::<SocketAddr>() {
+        Ok(addr) => addr,
+        Err(e) => {
+            warn!("Could not serve {what} on {address}: {e}");
+            return;
+        }
+    };
+    let make_service = make_service_fn(move |_| {
+        let handle = handle.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let response = handle(req);
+                async move { Ok::<_, Infallible>(response.await) }
+            }))
+        }
+    });
+    let server = match Server::try_bind(&addr) {
+        Ok(builder) => builder.serve(make_service),
+        Err(e) => {
+            warn!("Could not serve {what} on {address}: {e}");
+            return;
+        }
+    };
+    info!("Serving {what} on {addr}");
+    if let Err(e) = server.await {
+        warn!("Stopped serving {what}: {e}");
+    }
+}
+
+#[derive(Serialize)]
+struct ServerInfo {
+    name: String,
+    motd: String,
+    software: &'static str,
+    /// Seconds since the server started
+    uptime: u64,
+    players: usize,
+    limit: Option<i8>,
+}
+
+/// Position in blocks, with `y` at the feet for players
+#[derive(Serialize)]
+struct Position {
+    x: f64,
+    y: f64,
+    z: f64,
+}
+
+impl Position {
+    fn of(x: i32, y: i32, z: i32) -> Self {
+        let block = |v: i32| f64::from(v) / 32.0;
+        Self {
+            x: block(x),
+            y: block(y - PLAYER_HEIGHT),
+            z: block(z),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct PlayerInfo {
+    id: i8,
+    name: String,
+    rank: Rank,
+    world: String,
+    position: Position,
+    yaw: u8,
+    pitch: u8,
+}
+
+#[derive(Serialize)]
+struct Size {
+    x: i16,
+    y: i16,
+    z: i16,
+}
+
+#[derive(Serialize)]
+struct WorldInfo {
+    name: String,
+    size: Size,
+    spawn: Position,
+    players: usize,
+}
+
+#[derive(Deserialize)]
+struct Broadcast {
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct Kick {
+    player: String,
+    reason: Option<String>,
+}
+
+/// Replies of the command run for a POST request
+#[derive(Serialize)]
+struct Output {
+    output: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct Error {
+    error: String,
+}
+
+async fn handle(req: Request<Body>, token: &str, state: &State) -> Response<Body> {
+    let method = req.method().clone();
+    let path = req.uri().path().trim_end_matches('/').to_string();
+    match (method, path.as_str()) {
+        (Method::GET, "/api/info") => json(StatusCode::OK, &server_info(state).await),
+        (Method::GET, "/api/players") => json(StatusCode::OK, &players(state).await),
+        (Method::GET, "/api/worlds") => json(StatusCode::OK, &worlds(state).await),
+        (Method::GET, "/metrics") => metrics_response(state).await,
+        (Method::POST, "/api/broadcast" | "/api/kick" | "/api/save") => {
+            let authorized = req
+                .headers()
+                .get(header::AUTHORIZATION)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.strip_prefix("Bearer "))
+                .is_some_and(|t| secret::matches(t.trim().as_bytes(), token.as_bytes()));
+            if !authorized {
+                return error(StatusCode::UNAUTHORIZED, "Missing or wrong token");
+            }
+            match path.as_str() {
+                "/api/broadcast" => match read_json::<Broadcast>(req).await {
+                    Ok(b) if b.message.trim().is_empty() => {
+                        error(StatusCode::BAD_REQUEST, "The message is empty")
+                    }
+                    Ok(b) => command(state, Command::Say(b.message.trim().to_string())).await,
+                    Err(e) => e,
+                },
+                "/api/kick" => match read_json::<Kick>(req).await {
+                    Ok(k) if !login::is_valid_name(&k.player) => {
+                        error(StatusCode::BAD_REQUEST, "Invalid player name")
+                    }
+                    Ok(k) => {
+                        let online = state
+                            .players
+                            .lock()
+                            .await
+                            .values()
+                            .any(|p| p.player_name.trim().eq_ignore_ascii_case(&k.player));
+                        if online {
+                            command(state, Command::Kick(k.player, k.reason)).await
+                        } else {
+                            error(StatusCode::NOT_FOUND, "The player is not online")
+                        }
+                    }
+                    Err(e) => e,
+                },
+                _ => command(state, Command::Save).await,
+            }
+        }
+        (
+            _,
+            "/api/info" | "/api/players" | "/api/worlds" | "/api/broadcast" | "/api/kick"
+            | "/api/save" | "/metrics",
+        ) => error(StatusCode::METHOD_NOT_ALLOWED, "Method not allowed"),
+        _ => error(StatusCode::NOT_FOUND, "Not found"),
+    }
+}
+
+async fn metrics_response(state: &State) -> Response<Body> {
+    let players = state.players.lock().await.len();
+    Response::builder()
+        .header(header::CONTENT_TYPE, "text/plain; version=0.0.4")
+        .body(Body::from(metrics::render(players)))
+        .unwrap()
+}
+
+async fn server_info(state: &State) -> ServerInfo {
+    let cli = state.cli.get();
+    // Commands lock the players before the world, never hold both in the other order
+    let players = state.players.lock().await.len();
+    let world = state.world.lock().await;
+    ServerInfo {
+        name: world.name.clone().unwrap_or_else(|| cli.name.clone()),
+        motd: world.motd.clone().unwrap_or_else(|| cli.motd.clone()),
+        software: heartbeat::SOFTWARE,
+        uptime: state.started.elapsed().as_secs(),
+        players,
+        limit: cli.limit,
+    }
+}
+
+async fn players(state: &State) -> Vec<PlayerInfo> {
+    let world = world_name(state).await;
+    let mut players = state
+        .players
+        .lock()
+        .await
+        .iter()
+        .map(|(id, p)| PlayerInfo {
+            id: *id,
+            name: p.player_name.trim().to_string(),
+            rank: p.rank,
+            world: world.clone(),
+            position: Position::of(p.x, p.y, p.z),
+            yaw: p.yaw,
+            pitch: p.pitch,
+        })
+        .collect::<Vec<_>>();
+    players.sort_by_key(|p| p.id);
+    players
+}
+
+async fn worlds(state: &State) -> Vec<WorldInfo> {
+    let name = world_name(state).await;
+    let players = state.players.lock().await.len();
+    let map = state.map.lock().await;
+    let (x, y, z) = map.size;
+    let spawn = map.spawn_point;
+    vec![WorldInfo {
+        name,
+        size: Size { x, y, z },
+        spawn: Position::of(spawn.0, spawn.1, spawn.2),
+        players,
+    }]
+}
+
+async fn world_name(state: &State) -> String {
+    let world = state.world.lock().await;
+    world
+        .name
+        .clone()
+        .unwrap_or_else(|| state.cli.get().name.clone())
+}
+
+/// Runs a command as the console and responds with its replies.
+async fn command(state: &State, cmd: Command) -> Response<Body> {
+    let mut sender = Sender::console(console::NAME);
+    run_command(&mut sender, cmd, state).await;
+    let output = sender
+        .take_lines()
+        .iter()
+        .map(|line| console::strip_colors(line))
+        .collect();
+    json(StatusCode::OK, &Output { output })
+}
+
+async fn read_json<T: DeserializeOwned>(req: Request<Body>) -> Result<T, Response<Body>> {
+    let too_large = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok()?.parse::<u64>().ok())
+        .is_none_or(|length| length > MAX_BODY_SIZE);
+    if too_large {
+        return Err(error(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "The body needs a Content-Length of at most 16 KiB",
+        ));
+    }
+    let body = hyper::body::to_bytes(req.into_body())
+        .await
+        .map_err(|e| error(StatusCode::BAD_REQUEST, &e.to_string()))?;
+    serde_json::from_slice(&body).map_err(|e| error(StatusCode::BAD_REQUEST, &e.to_string()))
+}
+
+fn json(status: StatusCode, value: &impl Serialize) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_vec(value).unwrap()))
+        .unwrap()
+}
+
+fn error(status: StatusCode, message: &str) -> Response<Body> {
+    json(
+        status,
+        &Error {
+            error: message.to_string(),
+        },
+    )
+}
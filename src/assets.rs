@@ -0,0 +1,337 @@
+/* This file is part of classicl-server.
+ *
+ * classicl-server is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use classicl::server::{DefineEffect, DefineModel, DefineModelPart, SpawnEffect};
+use serde::{Deserialize, Serialize};
+
+/// Fractional values of effects are sent multiplied by this
+const EFFECT_SCALE: f64 = 10000.0;
+/// Most custom models a client supports
+const MAX_MODELS: u8 = 64;
+
+/// Location of the world assets inside the data directory
+pub fn path(data: &Path) -> PathBuf {
+    data.join("assets.toml")
+}
+
+/// Particle effects and entity models of the world, editable in `assets.toml` inside the data
+/// directory
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Assets {
+    pub effects: Vec<Effect>,
+    pub models: Vec<Model>,
+    /// Name of the effect spawned where a block is broken
+    pub block_break_effect: Option<String>,
+}
+
+impl Assets {
+    /// Loads the assets from `path`, there are none if it does not exist.
+    pub fn load(path: &Path) -> Self {
+        if let Ok(s) = fs::read_to_string(path) {
+            toml::from_str(&s).unwrap()
+        } else {
+            Self::default()
+        }
+    }
+
+    pub fn effect_id(&self, name: &str) -> Option<u8> {
+        self.effects
+            .iter()
+            .position(|e| e.name == name)
+            .and_then(|id| id.try_into().ok())
+    }
+
+    pub fn has_model(&self, name: &str) -> bool {
+        self.models
+            .iter()
+            .take(MAX_MODELS.into())
+            .any(|m| m.name == name)
+    }
+
+    pub fn to_define_effects(&self) -> impl Iterator<Item = DefineEffect> + '_ {
+        self.effects
+            .iter()
+            .zip(0..=u8::MAX)
+            .map(|(e, id)| e.to_define_effect(id))
+    }
+
+    pub fn to_define_models(
+        &self,
+    ) -> impl Iterator<Item = (DefineModel, Vec<DefineModelPart>)> + '_ {
+        self.models
+            .iter()
+            .zip(0..MAX_MODELS)
+            .map(|(m, id)| m.to_packets(id))
+    }
+}
+
+/// Particles spawned together, sizes and distances are in blocks and times in seconds
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Effect {
+    pub name: String,
+    /// Corners of the texture in `particles.png`
+    pub texture: [u8; 4],
+    #[serde(default = "white")]
+    pub tint: [u8; 3],
+    #[serde(default = "one")]
+    pub frame_count: u8,
+    pub particle_count: u8,
+    pub size: f64,
+    #[serde(default)]
+    pub size_variation: f64,
+    pub spread: f64,
+    pub speed: f64,
+    #[serde(default)]
+    pub gravity: f64,
+    pub lifetime: f64,
+    #[serde(default)]
+    pub lifetime_variation: f64,
+    #[serde(default)]
+    pub collide_liquid: bool,
+    #[serde(default)]
+    pub collide_solid: bool,
+    #[serde(default)]
+    pub collide_leaves: bool,
+    /// Removes particles once they hit the ground
+    #[serde(default)]
+    pub expire_on_ground: bool,
+    #[serde(default)]
+    pub full_bright: bool,
+}
+
+fn white() -> [u8; 3] {
+    [0xff; 3]
+}
+
+fn one() -> u8 {
+    1
+}
+
+impl Effect {
+    pub fn to_define_effect(&self, id: u8) -> DefineEffect {
+        let scaled = |v: f64| (v * EFFECT_SCALE).round() as i32;
+        let [u1, v1, u2, v2] = self.texture;
+        let [red_tint, green_tint, blue_tint] = self.tint;
+        DefineEffect {
+            effect_id: id,
+            u1,
+            v1,
+            u2,
+            v2,
+            red_tint,
+            green_tint,
+            blue_tint,
+            frame_count: self.frame_count,
+            particle_count: self.particle_count,
+            size: (self.size * 32.0).round() as u8,
+            size_variation: scaled(self.size_variation),
+            spread: (self.spread * 32.0).round() as u16,
+            speed: scaled(self.speed),
+            gravity: scaled(self.gravity),
+            base_lifetime: scaled(self.lifetime),
+            lifetime_variation: scaled(self.lifetime_variation),
+            collide_flags: u8::from(self.collide_leaves) << 4
+                | u8::from(self.collide_liquid) << 5
+                | u8::from(self.collide_solid) << 6
+                | u8::from(self.expire_on_ground) << 7,
+            full_bright: self.full_bright.into(),
+        }
+    }
+}
+
+/// Spawns an effect at a fixed-point position with particles moving away from `origin`.
+pub fn spawn_effect(id: u8, (x, y, z): (i32, i32, i32), origin: (i32, i32, i32)) -> SpawnEffect {
+    SpawnEffect {
+        effect_id: id,
+        x,
+        y,
+        z,
+        origin_x: origin.0,
+        origin_y: origin.1,
+        origin_z: origin.2,
+    }
+}
+
+/// Entity model made of boxes, coordinates are in 1/16 blocks
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Model {
+    pub name: String,
+    #[serde(default)]
+    pub bobbing: bool,
+    #[serde(default)]
+    pub pushes: bool,
+    #[serde(default)]
+    pub uses_human_skin: bool,
+    #[serde(default)]
+    pub calc_human_anims: bool,
+    pub name_y: f32,
+    pub eye_y: f32,
+    pub collision_bounds: [f32; 3],
+    pub picking_bounds_min: [f32; 3],
+    pub picking_bounds_max: [f32; 3],
+    /// Size of the texture
+    pub texture_size: [u16; 2],
+    pub parts: Vec<ModelPart>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ModelPart {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+    /// Texture corners of the top, bottom, front, back, left and right face
+    pub uv: [[u16; 4]; 6],
+    #[serde(default)]
+    pub rotation_origin: [f32; 3],
+    #[serde(default)]
+    pub rotation: [f32; 3],
+    /// Up to four animations applied to the part
+    #[serde(default)]
+    pub anims: Vec<Animation>,
+    #[serde(default)]
+    pub full_bright: bool,
+    #[serde(default)]
+    pub first_person_arm: bool,
+}
+
+/// Animation of a [`ModelPart`], see the CustomModels extension for the meaning of the arguments
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Animation {
+    pub kind: u8,
+    pub axis: u8,
+    pub args: [f32; 4],
+}
+
+impl Model {
+    /// Packets defining the model with the given id, followed by all of its parts
+    pub fn to_packets(&self, id: u8) -> (DefineModel, Vec<DefineModelPart>) {
+        let [collision_x, collision_y, collision_z] = self.collision_bounds;
+        let [picking_min_x, picking_min_y, picking_min_z] = self.picking_bounds_min;
+        let [picking_max_x, picking_max_y, picking_max_z] = self.picking_bounds_max;
+        let model = DefineModel {
+            model_id: id,
+            name: self.name.clone(),
+            flags: u8::from(self.bobbing)
+                | u8::from(self.pushes) << 1
+                | u8::from(self.uses_human_skin) << 2
+                | u8::from(self.calc_human_anims) << 3,
+            name_y: self.name_y,
+            eye_y: self.eye_y,
+            collision_x,
+            collision_y,
+            collision_z,
+            picking_min_x,
+            picking_min_y,
+            picking_min_z,
+            picking_max_x,
+            picking_max_y,
+            picking_max_z,
+            u_scale: self.texture_size[0],
+            v_scale: self.texture_size[1],
+            parts_count: self.parts.len() as u8,
+        };
+        let parts = self
+            .parts
+            .iter()
+            .map(|p| p.to_define_model_part(id))
+            .collect();
+        (model, parts)
+    }
+}
+
+impl ModelPart {
+    fn to_define_model_part(&self, id: u8) -> DefineModelPart {
+        let [min_x, min_y, min_z] = self.min;
+        let [max_x, max_y, max_z] = self.max;
+        let [top, bottom, front, back, left, right] = self.uv;
+        let [rotation_origin_x, rotation_origin_y, rotation_origin_z] = self.rotation_origin;
+        let [rotation_x, rotation_y, rotation_z] = self.rotation;
+        let anim = |i: usize| {
+            self.anims
+                .get(i)
+                .map_or((0, [0.0; 4]), |a| ((a.kind & 0x3f) | a.axis << 6, a.args))
+        };
+        let (anim1_flags, [anim1_a, anim1_b, anim1_c, anim1_d]) = anim(0);
+        let (anim2_flags, [anim2_a, anim2_b, anim2_c, anim2_d]) = anim(1);
+        let (anim3_flags, [anim3_a, anim3_b, anim3_c, anim3_d]) = anim(2);
+        let (anim4_flags, [anim4_a, anim4_b, anim4_c, anim4_d]) = anim(3);
+        DefineModelPart {
+            model_id: id,
+            min_x,
+            min_y,
+            min_z,
+            max_x,
+            max_y,
+            max_z,
+            top_u1: top[0],
+            top_v1: top[1],
+            top_u2: top[2],
+            top_v2: top[3],
+            bottom_u1: bottom[0],
+            bottom_v1: bottom[1],
+            bottom_u2: bottom[2],
+            bottom_v2: bottom[3],
+            front_u1: front[0],
+            front_v1: front[1],
+            front_u2: front[2],
+            front_v2: front[3],
+            back_u1: back[0],
+            back_v1: back[1],
+            back_u2: back[2],
+            back_v2: back[3],
+            left_u1: left[0],
+            left_v1: left[1],
+            left_u2: left[2],
+            left_v2: left[3],
+            right_u1: right[0],
+            right_v1: right[1],
+            right_u2: right[2],
+            right_v2: right[3],
+            rotation_origin_x,
+            rotation_origin_y,
+            rotation_origin_z,
+            rotation_x,
+            rotation_y,
+            rotation_z,
+            anim1_flags,
+            anim1_a,
+            anim1_b,
+            anim1_c,
+            anim1_d,
+            anim2_flags,
+            anim2_a,
+            anim2_b,
+            anim2_c,
+            anim2_d,
+            anim3_flags,
+            anim3_a,
+            anim3_b,
+            anim3_c,
+            anim3_d,
+            anim4_flags,
+            anim4_a,
+            anim4_b,
+            anim4_c,
+            anim4_d,
+            flags: u8::from(self.full_bright) | u8::from(self.first_person_arm) << 1,
+        }
+    }
+}
@@ -0,0 +1,196 @@
+/* This file is part of classicl-server.
+ *
+ * classicl-server is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+
+use crate::commands;
+
+/// Location of the ban lists inside the data directory
+pub fn path(data: &Path) -> PathBuf {
+    data.join("bans.toml")
+}
+
+/// Parses an IP address or a CIDR range like `192.168.0.0/16`.
+pub fn parse_net(s: &str) -> Option<IpNet> {
+    s.parse::<IpNet>()
+        .map(|net| net.trunc())
+        .or_else(|_| s.parse::<IpAddr>().map(IpNet::from))
+        .ok()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Ban {
+    /// Name of whoever issued the ban
+    pub by: String,
+    pub reason: Option<String>,
+    /// Seconds since the Unix epoch at which the ban is lifted, permanent if missing
+    pub expires: Option<u64>,
+}
+
+impl Ban {
+    pub fn new(by: &str, reason: Option<String>, duration: Option<Duration>) -> Self {
+        Self {
+            by: by.to_string(),
+            reason,
+            expires: duration.map(|d| now().saturating_add(d.as_secs())),
+        }
+    }
+
+    fn is_expired(&self) -> bool {
+        matches!(self.expires, Some(expires) if expires <= now())
+    }
+
+    /// Message shown to the banned player when they are disconnected
+    pub fn message(&self) -> String {
+        let mut message = match self.expires {
+            Some(expires) => format!(
+                "Banned for {}",
+                commands::format_duration(Duration::from_secs(expires.saturating_sub(now())))
+            ),
+            None => "Banned".to_string(),
+        };
+        if let Some(reason) = &self.reason {
+            message.push_str(": ");
+            message.push_str(reason);
+        }
+        message
+    }
+}
+
+/// Banned names and addresses, saved in `bans.toml` inside the data directory
+///
+/// Expired bans are removed when they are looked up.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Bans {
+    #[serde(skip)]
+    path: PathBuf,
+    /// Bans by lowercase player name
+    names: BTreeMap<String, Ban>,
+    /// Bans by IP address or CIDR range
+    ips: BTreeMap<String, Ban>,
+}
+
+impl Bans {
+    pub fn load(path: &Path) -> Self {
+        let mut bans: Self = if let Ok(s) = fs::read_to_string(path) {
+            toml::from_str(&s).unwrap()
+        } else {
+            Self::default()
+        };
+        bans.path = path.to_path_buf();
+        bans
+    }
+
+    fn save(&self) {
+        fs::write(&self.path, toml::to_string_pretty(self).unwrap()).unwrap();
+    }
+
+    /// Ban of the given name, if it is banned
+    pub fn name(&mut self, name: &str) -> Option<Ban> {
+        let name = name.to_lowercase();
+        let ban = self.names.get(&name)?;
+        if ban.is_expired() {
+            self.names.remove(&name);
+            self.save();
+            None
+        } else {
+            Some(ban.clone())
+        }
+    }
+
+    /// Ban of the first address or range containing `ip`, if there is one
+    pub fn ip(&mut self, ip: IpAddr) -> Option<Ban> {
+        let (key, ban) = self
+            .ips
+            .iter()
+            .find(|(net, _)| parse_net(net).is_some_and(|net| net.contains(&ip)))?;
+        if ban.is_expired() {
+            let key = key.clone();
+            self.ips.remove(&key);
+            self.save();
+            // Another range might still contain the address
+            self.ip(ip)
+        } else {
+            Some(ban.clone())
+        }
+    }
+
+    pub fn ban_name(&mut self, name: &str, ban: Ban) {
+        self.names.insert(name.to_lowercase(), ban);
+        self.save();
+    }
+
+    pub fn ban_ip(&mut self, net: IpNet, ban: Ban) {
+        self.ips.insert(net.to_string(), ban);
+        self.save();
+    }
+
+    /// Lifts the ban of a name, address or range, returns whether there was one.
+    pub fn unban(&mut self, target: &str) -> bool {
+        let removed = match parse_net(target) {
+            Some(net) => self.ips.remove(&net.to_string()).is_some(),
+            None => self.names.remove(&target.to_lowercase()).is_some(),
+        };
+        if removed {
+            self.save();
+        }
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nets_are_addresses_or_ranges() {
+        assert_eq!(parse_net("10.0.0.1").unwrap().to_string(), "10.0.0.1/32");
+        assert_eq!(parse_net("::1").unwrap().to_string(), "::1/128");
+        assert_eq!(
+            parse_net("192.168.5.7/16").unwrap().to_string(),
+            "192.168.0.0/16"
+        );
+        for bad in ["", "bob", "10.0.0", "10.0.0.1/33", "10.0.0.0/-1"] {
+            assert!(parse_net(bad).is_none(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn ranges_contain_addresses() {
+        let mut bans = Bans::default();
+        bans.ips
+            .insert("192.168.0.0/16".into(), Ban::new("admin", None, None));
+        assert!(bans.ip("192.168.44.1".parse().unwrap()).is_some());
+        assert!(bans.ip("192.169.0.1".parse().unwrap()).is_none());
+    }
+}
@@ -0,0 +1,109 @@
+/* This file is part of classicl-server.
+ *
+ * classicl-server is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{collections::HashMap, time::Duration};
+
+use classicl::{server::BulkBlockUpdate, stats::STATS};
+use log::debug;
+use tokio::sync::Mutex;
+
+use crate::{
+    cpe,
+    terrain::{blocks, Terrain},
+    Player,
+};
+
+/// Time between two flushes of the collected block changes
+pub const TICK: Duration = Duration::from_millis(50);
+/// Clients without BulkBlockUpdate get the whole level again when more blocks changed in a tick
+const RESEND_THRESHOLD: usize = 4096;
+
+/// Block changes collected during a tick, only the last change of a block is kept
+#[derive(Debug, Default)]
+pub struct BlockChanges(HashMap<(i16, i16, i16), u16>);
+
+impl BlockChanges {
+    /// Queues a change of a block which is already applied to the terrain.
+    pub fn insert(&mut self, pos: (i16, i16, i16), block_type: u16) {
+        self.0.insert(pos, block_type);
+    }
+}
+
+/// Sends the changes of the last tick to every player in the least number of packets their
+/// client supports.
+pub async fn flush(
+    changes: &Mutex<BlockChanges>,
+    players: &Mutex<HashMap<i8, Player>>,
+    map: &Mutex<Terrain>,
+) {
+    let players = players.lock().await;
+    let changes = std::mem::take(&mut changes.lock().await.0);
+    if changes.is_empty() {
+        return;
+    }
+    let map = map.lock().await;
+
+    let mut updates = vec![];
+    let mut update = BulkBlockUpdate::default();
+    for (&(x, y, z), &block_type) in &changes {
+        if let Some(index) = map.index_of(x, y, z) {
+            update.indices.push(index as i32);
+            update.blocks.push(block_type);
+        }
+        if update.indices.len() == BulkBlockUpdate::MAX_BLOCKS {
+            updates.push(std::mem::take(&mut update));
+        }
+    }
+    if !update.indices.is_empty() {
+        updates.push(update);
+    }
+
+    for (_, p) in players.iter() {
+        if p.ext.has(cpe::BULK_BLOCK_UPDATE) {
+            let buf = if p.ext.has(cpe::EXTENDED_BLOCKS) {
+                updates.iter().flat_map(|u| u.to_bytes(true)).collect()
+            } else {
+                updates
+                    .iter()
+                    .flat_map(|u| {
+                        let blocks = u
+                            .blocks
+                            .iter()
+                            .map(|&b| u8::try_from(b).unwrap_or(blocks::FALLBACK).into());
+                        BulkBlockUpdate {
+                            indices: u.indices.clone(),
+                            blocks: blocks.collect(),
+                        }
+                        .to_bytes(false)
+                    })
+                    .collect()
+            };
+            STATS.send_packets(BulkBlockUpdate::ID, updates.len());
+            if let Err(e) = p.c.write_bytes(buf).await {
+                debug!(
+                    "Could not send block changes to {}: {e}",
+                    p.player_name.trim()
+                );
+            }
+        } else if changes.len() > RESEND_THRESHOLD {
+            p.resend_level(&map).await;
+        } else {
+            for (&(x, y, z), &block_type) in &changes {
+                p.write_block(x, y, z, block_type).await;
+            }
+        }
+    }
+}
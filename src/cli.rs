@@ -17,8 +17,13 @@
 use clap::Parser;
 use std::path::PathBuf;
 
+use crate::{login::DuplicateLogin, ranks::Rank};
+
 /// A Block Game Server
-#[derive(Parser, Debug)]
+///
+/// Every flag except the data directory can also be set in `config.toml` inside the data
+/// directory, flags given on the command line take precedence.
+#[derive(Parser, Clone, Debug)]
 #[clap(author, version, about, long_about = None)]
 pub struct Cli {
     /// Address to listen on
@@ -60,4 +65,57 @@ pub struct Cli {
     #[clap(short, long, value_parser)]
     /// Player limit
     pub limit: Option<i8>,
+
+    /// Server list to send heartbeats to, joining names are verified with it once set
+    #[clap(long, value_parser)]
+    pub heartbeat_url: Option<String>,
+
+    /// Show the server publicly on the server list
+    #[clap(long, value_parser)]
+    pub public: bool,
+
+    /// What to do when a name logs in while it is already online
+    #[clap(long, value_enum, default_value_t = DuplicateLogin::KickOld)]
+    pub duplicate_login: DuplicateLogin,
+
+    /// Address to accept RCON connections on, authenticated with the password stored in
+    /// `rcon_password` inside the data directory
+    #[clap(long, value_parser)]
+    pub rcon_address: Option<String>,
+
+    /// Address to serve the HTTP JSON API and Prometheus metrics on, POST requests need the token
+    /// stored in `api_token` inside the data directory
+    #[clap(long, value_parser)]
+    pub api_address: Option<String>,
+
+    /// Address to serve only the Prometheus metrics on, they are also part of the HTTP API
+    #[clap(long, value_parser)]
+    pub metrics_address: Option<String>,
+
+    /// Seconds between saves of a changed map, 0 only saves when the server stops
+    #[clap(long, value_parser, default_value_t = 120)]
+    pub autosave: u64,
+
+    /// Rank of players who were never given one
+    #[clap(long, value_enum, default_value_t = Rank::Guest)]
+    pub default_rank: Rank,
+
+    /// Shown to players who can not join because the server is full, `{server}` is replaced by
+    /// the server name
+    #[clap(long, value_parser, default_value_t = String::from("&cSorry, {server} &cis full right now."))]
+    pub full_message: String,
+
+    /// Announced when a player joins, `{player}` is replaced by their name
+    #[clap(long, value_parser)]
+    pub join_message: Option<String>,
+
+    /// Announced when a player leaves, `{player}` is replaced by their name
+    #[clap(long, value_parser)]
+    pub leave_message: Option<String>,
+}
+
+impl Cli {
+    pub fn full_message(&self) -> String {
+        self.full_message.replace("{server}", &self.name)
+    }
 }
@@ -14,38 +14,1126 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use std::{fmt::Display, time::Duration};
+
+use crate::{login, ranks::Rank, terrain::blocks};
+
 pub enum Command {
-    Tp(String),
-}
-
-impl Command {
-    pub fn from_str(s: &str) -> Result<Self, CommandError> {
-        let split = s.split(' ').collect::<Vec<&str>>();
-
-        if let Some(cmd) = split.first() {
-            match *cmd {
-                "tp" => {
-                    if let Some(player) = split.get(1) {
-                        if split.get(2).is_none() {
-                            Ok(Self::Tp(player.to_string()))
-                        } else {
-                            Err(CommandError::TooManyArguments)
-                        }
-                    } else {
-                        Err(CommandError::NotEnoughArguments)
-                    }
+    Tp(TpTarget),
+    Select,
+    Deselect,
+    BlockInfo,
+    Hold(u16),
+    /// Changes the model of the named player or the sender
+    Model(String, Option<String>),
+    Scale(f64, Option<String>),
+    Rotate((i32, i32, i32), Option<String>),
+    /// Shows the latency of the named player or the sender
+    Ping(Option<String>),
+    /// Puts a block into a hotbar slot, counted from 1
+    Hotbar(u8, u16),
+    /// Sets the velocity of the named player or the sender in blocks per tick
+    Launch((f64, f64, f64), Option<String>),
+    /// Makes the current position the respawn point of the sender
+    Checkpoint,
+    /// Changes the MOTD of the world
+    Motd(String),
+    /// Shows or hides cinematic bars for the named player or the sender
+    Cinematic(bool, Option<String>),
+    /// Spawns a custom particle effect at the position of the sender
+    Effect(String),
+    /// Replaces every block of the selection
+    Fill(u16),
+    /// Raises the rank of the named player by one
+    Promote(String),
+    /// Lowers the rank of the named player by one
+    Demote(String),
+    /// Bans a player by name with an optional reason
+    Ban(String, Option<String>),
+    /// Bans a player by name for a while with an optional reason
+    TempBan(String, Duration, Option<String>),
+    /// Bans an IP address, a CIDR range or the address of the named player
+    BanIp(String, Option<String>),
+    /// Lifts the ban of a name, IP address or CIDR range
+    Unban(String),
+    Whitelist(WhitelistAction),
+    /// Lists the usable commands or explains the named one
+    Help(Option<String>),
+    /// Sends a private message to the named player
+    Msg(String, String),
+    /// Answers the last private message
+    Reply(String),
+    /// Describes an action of the sender in the chat
+    Me(String),
+    Who,
+    /// Tells when the named player was last online
+    Seen(String),
+    /// Disconnects the named player with an optional reason
+    Kick(String, Option<String>),
+    /// Stops the named player from chatting for a while
+    Mute(String, Duration),
+    Unmute(String),
+    /// Stops or lets the named player move again
+    Freeze(String),
+    /// Teleports the named player, or everyone if there is none, to the sender
+    Summon(Option<String>),
+    /// Teleports the sender to the spawn point of the world
+    Spawn,
+    /// Makes the position of the sender the spawn point of the world
+    SetSpawn,
+    Home,
+    SetHome,
+    /// Teleports the sender to the named warp, or lists all warps if there is no name
+    Warp(Option<String>),
+    SetWarp(String),
+    DelWarp(String),
+    /// Saves everything and shuts the server down
+    Stop,
+    /// Saves the map right away
+    Save,
+    /// Announces a message to every player
+    Say(String),
+    /// Reads `config.toml` again and applies what can change while running
+    Reload,
+}
+
+pub enum TpTarget {
+    Player(String),
+    /// Block coordinates the feet of the sender end up at
+    Position(Coordinate, Coordinate, Coordinate),
+}
+
+pub enum WhitelistAction {
+    Add(String),
+    Remove(String),
+    List,
+    On,
+    Off,
+}
+
+/// Block coordinate given as a number or relative to the sender with `~`
+#[derive(Clone, Copy, Debug)]
+pub enum Coordinate {
+    Absolute(i32),
+    Relative(i32),
+}
+
+impl Coordinate {
+    fn parse(word: &str) -> Option<Self> {
+        match word.strip_prefix('~') {
+            Some("") => Some(Self::Relative(0)),
+            Some(offset) => offset.parse().ok().map(Self::Relative),
+            None => word.parse().ok().map(Self::Absolute),
+        }
+    }
+
+    pub fn resolve(self, base: i32) -> i32 {
+        match self {
+            Self::Absolute(c) => c,
+            Self::Relative(offset) => base.saturating_add(offset),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ArgKind {
+    /// A valid player name, who does not need to be online
+    Player,
+    /// An integer inside the given range
+    Integer(i32, i32),
+    /// A finite decimal number
+    Number,
+    Coordinate,
+    /// A block id or the name of a default block
+    Block,
+    /// A duration like `10m`, see [`parse_duration`]
+    Duration,
+    /// A single word, or several words in double quotes
+    Word,
+    /// One of the given words
+    Choice(&'static [&'static str]),
+    /// Everything that follows, without surrounding double quotes
+    Rest,
+}
+
+pub struct Arg {
+    pub name: &'static str,
+    pub kind: ArgKind,
+    /// Optional arguments can only be followed by optional arguments.
+    pub optional: bool,
+}
+
+const fn arg(name: &'static str, kind: ArgKind) -> Arg {
+    Arg {
+        name,
+        kind,
+        optional: false,
+    }
+}
+
+const fn opt(name: &'static str, kind: ArgKind) -> Arg {
+    Arg {
+        name,
+        kind,
+        optional: true,
+    }
+}
+
+impl Display for Arg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self.kind {
+            ArgKind::Choice(choices) => choices.join("|"),
+            _ => self.name.to_string(),
+        };
+        if self.optional {
+            write!(f, "[{name}]")
+        } else {
+            write!(f, "<{name}>")
+        }
+    }
+}
+
+enum Value {
+    Text(String),
+    Integer(i32),
+    Number(f64),
+    Coordinate(Coordinate),
+    Block(u16),
+    Duration(Duration),
+}
+
+impl ArgKind {
+    /// Parses a single argument, blocks above `max_block` are invalid.
+    fn parse(self, word: &str, max_block: u16) -> Option<Value> {
+        match self {
+            Self::Player => Some(word)
+                .filter(|w| login::is_valid_name(w))
+                .map(|w| Value::Text(w.to_string())),
+            Self::Integer(min, max) => word
+                .parse()
+                .ok()
+                .filter(|i| (min..=max).contains(i))
+                .map(Value::Integer),
+            Self::Number => word
+                .parse()
+                .ok()
+                .filter(|n: &f64| n.is_finite())
+                .map(Value::Number),
+            Self::Coordinate => Coordinate::parse(word).map(Value::Coordinate),
+            Self::Block => word
+                .parse()
+                .ok()
+                .or_else(|| blocks::from_name(word).map(u16::from))
+                .filter(|&b| b <= max_block)
+                .map(Value::Block),
+            Self::Duration => parse_duration(word).map(Value::Duration),
+            Self::Word | Self::Rest => Some(Value::Text(word.to_string())),
+            Self::Choice(choices) => choices
+                .iter()
+                .find(|c| c.eq_ignore_ascii_case(word))
+                .map(|c| Value::Text(c.to_string())),
+        }
+    }
+}
+
+/// Parsed arguments handed to [`CommandInfo::build`] in the order of the schema
+struct Args(std::vec::IntoIter<Option<Value>>);
+
+impl Args {
+    fn opt_text(&mut self) -> Option<String> {
+        match self.0.next().flatten() {
+            Some(Value::Text(t)) => Some(t),
+            _ => None,
+        }
+    }
+
+    fn text(&mut self) -> String {
+        self.opt_text().unwrap()
+    }
+
+    fn integer(&mut self) -> i32 {
+        match self.0.next().flatten() {
+            Some(Value::Integer(i)) => i,
+            _ => unreachable!(),
+        }
+    }
+
+    fn number(&mut self) -> f64 {
+        match self.0.next().flatten() {
+            Some(Value::Number(n)) => n,
+            _ => unreachable!(),
+        }
+    }
+
+    fn opt_coordinate(&mut self) -> Option<Coordinate> {
+        match self.0.next().flatten() {
+            Some(Value::Coordinate(c)) => Some(c),
+            _ => None,
+        }
+    }
+
+    fn block(&mut self) -> u16 {
+        match self.0.next().flatten() {
+            Some(Value::Block(b)) => b,
+            _ => unreachable!(),
+        }
+    }
+
+    fn duration(&mut self) -> Duration {
+        match self.0.next().flatten() {
+            Some(Value::Duration(d)) => d,
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Who can run a command, regardless of rank
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Runner {
+    Anyone,
+    /// Commands which need the position or the client of the sender
+    Player,
+    /// Commands which control the whole server
+    Console,
+}
+
+impl Runner {
+    pub fn allows(self, is_player: bool) -> bool {
+        match self {
+            Self::Anyone => true,
+            Self::Player => is_player,
+            Self::Console => !is_player,
+        }
+    }
+}
+
+/// Everything the server knows about a command
+pub struct CommandInfo {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub args: &'static [Arg],
+    /// Lowest rank allowed to use the command, unless `permissions.toml` says otherwise
+    pub rank: Rank,
+    pub runner: Runner,
+    pub help: &'static str,
+    /// Turns valid arguments into the command, `None` if they do not fit together
+    build: fn(&mut Args) -> Option<Command>,
+}
+
+impl CommandInfo {
+    pub fn usage(&self) -> String {
+        let mut usage = format!("/{}", self.name);
+        for arg in self.args {
+            usage.push_str(&format!(" {arg}"));
+        }
+        usage
+    }
+}
+
+const PLAYER: Arg = arg("player", ArgKind::Player);
+const OPT_PLAYER: Arg = opt("player", ArgKind::Player);
+
+/// Every command of the server
+pub static COMMANDS: &[CommandInfo] = &[
+    CommandInfo {
+        name: "help",
+        aliases: &["commands"],
+        args: &[opt("command", ArgKind::Word)],
+        rank: Rank::Guest,
+        runner: Runner::Anyone,
+        help: "Lists the commands you can use or explains one of them",
+        build: |a| Some(Command::Help(a.opt_text())),
+    },
+    CommandInfo {
+        name: "tp",
+        aliases: &["teleport"],
+        args: &[
+            arg("player|x", ArgKind::Word),
+            opt("y", ArgKind::Coordinate),
+            opt("z", ArgKind::Coordinate),
+        ],
+        rank: Rank::Guest,
+        runner: Runner::Player,
+        help: "Teleports you to a player or to block coordinates, ~ is your position",
+        build: |a| {
+            let first = a.text();
+            let target = match (a.opt_coordinate(), a.opt_coordinate()) {
+                (Some(y), Some(z)) => TpTarget::Position(Coordinate::parse(&first)?, y, z),
+                (None, None) if login::is_valid_name(&first) => TpTarget::Player(first),
+                _ => return None,
+            };
+            Some(Command::Tp(target))
+        },
+    },
+    CommandInfo {
+        name: "select",
+        aliases: &[],
+        args: &[],
+        rank: Rank::Builder,
+        runner: Runner::Player,
+        help: "Starts marking a cuboid by placing or breaking two blocks",
+        build: |_| Some(Command::Select),
+    },
+    CommandInfo {
+        name: "deselect",
+        aliases: &[],
+        args: &[],
+        rank: Rank::Builder,
+        runner: Runner::Player,
+        help: "Clears your selection",
+        build: |_| Some(Command::Deselect),
+    },
+    CommandInfo {
+        name: "binfo",
+        aliases: &["blockinfo"],
+        args: &[],
+        rank: Rank::Guest,
+        runner: Runner::Player,
+        help: "Toggles showing information about clicked blocks and players",
+        build: |_| Some(Command::BlockInfo),
+    },
+    CommandInfo {
+        name: "hold",
+        aliases: &[],
+        args: &[arg("block", ArgKind::Block)],
+        rank: Rank::Guest,
+        runner: Runner::Player,
+        help: "Puts a block into your hand",
+        build: |a| Some(Command::Hold(a.block())),
+    },
+    CommandInfo {
+        name: "model",
+        aliases: &[],
+        args: &[arg("model", ArgKind::Word), OPT_PLAYER],
+        rank: Rank::Moderator,
+        runner: Runner::Anyone,
+        help: "Changes the model of a player or yourself",
+        build: |a| Some(Command::Model(a.text(), a.opt_text())),
+    },
+    CommandInfo {
+        name: "scale",
+        aliases: &[],
+        args: &[arg("scale", ArgKind::Number), OPT_PLAYER],
+        rank: Rank::Moderator,
+        runner: Runner::Anyone,
+        help: "Resizes the model of a player or yourself",
+        build: |a| {
+            let scale = a.number();
+            Some(Command::Scale(scale, a.opt_text())).filter(|_| scale > 0.0)
+        },
+    },
+    CommandInfo {
+        name: "rotate",
+        aliases: &[],
+        args: &[
+            arg("x", ArgKind::Integer(-360, 360)),
+            arg("y", ArgKind::Integer(-360, 360)),
+            arg("z", ArgKind::Integer(-360, 360)),
+            OPT_PLAYER,
+        ],
+        rank: Rank::Moderator,
+        runner: Runner::Anyone,
+        help: "Rotates the model of a player or yourself in degrees",
+        build: |a| {
+            Some(Command::Rotate(
+                (a.integer(), a.integer(), a.integer()),
+                a.opt_text(),
+            ))
+        },
+    },
+    CommandInfo {
+        name: "ping",
+        aliases: &[],
+        args: &[OPT_PLAYER],
+        rank: Rank::Guest,
+        runner: Runner::Anyone,
+        help: "Shows the latency of a player or yourself",
+        build: |a| Some(Command::Ping(a.opt_text())),
+    },
+    CommandInfo {
+        name: "hotbar",
+        aliases: &[],
+        args: &[
+            arg("slot", ArgKind::Integer(1, 9)),
+            arg("block", ArgKind::Block),
+        ],
+        rank: Rank::Guest,
+        runner: Runner::Player,
+        help: "Puts a block into a slot of your hotbar",
+        build: |a| Some(Command::Hotbar(a.integer() as u8, a.block())),
+    },
+    CommandInfo {
+        name: "launch",
+        aliases: &[],
+        args: &[
+            arg("x", ArgKind::Number),
+            arg("y", ArgKind::Number),
+            arg("z", ArgKind::Number),
+            OPT_PLAYER,
+        ],
+        rank: Rank::Moderator,
+        runner: Runner::Anyone,
+        help: "Sets the velocity of a player or yourself in blocks per tick",
+        build: |a| {
+            Some(Command::Launch(
+                (a.number(), a.number(), a.number()),
+                a.opt_text(),
+            ))
+        },
+    },
+    CommandInfo {
+        name: "checkpoint",
+        aliases: &[],
+        args: &[],
+        rank: Rank::Guest,
+        runner: Runner::Player,
+        help: "Makes your position the point you respawn at",
+        build: |_| Some(Command::Checkpoint),
+    },
+    CommandInfo {
+        name: "motd",
+        aliases: &[],
+        args: &[arg("motd", ArgKind::Rest)],
+        rank: Rank::Admin,
+        runner: Runner::Anyone,
+        help: "Changes the MOTD of the world",
+        build: |a| Some(Command::Motd(a.text())),
+    },
+    CommandInfo {
+        name: "cinematic",
+        aliases: &[],
+        args: &[arg("state", ArgKind::Choice(&["on", "off"])), OPT_PLAYER],
+        rank: Rank::Moderator,
+        runner: Runner::Anyone,
+        help: "Shows or hides cinematic bars for a player or yourself",
+        build: |a| Some(Command::Cinematic(a.text() == "on", a.opt_text())),
+    },
+    CommandInfo {
+        name: "effect",
+        aliases: &[],
+        args: &[arg("effect", ArgKind::Word)],
+        rank: Rank::Moderator,
+        runner: Runner::Player,
+        help: "Spawns a particle effect at your position",
+        build: |a| Some(Command::Effect(a.text())),
+    },
+    CommandInfo {
+        name: "fill",
+        aliases: &[],
+        args: &[arg("block", ArgKind::Block)],
+        rank: Rank::Moderator,
+        runner: Runner::Player,
+        help: "Replaces every block of your selection",
+        build: |a| Some(Command::Fill(a.block())),
+    },
+    CommandInfo {
+        name: "promote",
+        aliases: &[],
+        args: &[PLAYER],
+        rank: Rank::Admin,
+        runner: Runner::Anyone,
+        help: "Raises the rank of a player by one",
+        build: |a| Some(Command::Promote(a.text())),
+    },
+    CommandInfo {
+        name: "demote",
+        aliases: &[],
+        args: &[PLAYER],
+        rank: Rank::Admin,
+        runner: Runner::Anyone,
+        help: "Lowers the rank of a player by one",
+        build: |a| Some(Command::Demote(a.text())),
+    },
+    CommandInfo {
+        name: "ban",
+        aliases: &[],
+        args: &[PLAYER, opt("reason", ArgKind::Rest)],
+        rank: Rank::Moderator,
+        runner: Runner::Anyone,
+        help: "Bans a player by name",
+        build: |a| Some(Command::Ban(a.text(), a.opt_text())),
+    },
+    CommandInfo {
+        name: "tempban",
+        aliases: &[],
+        args: &[
+            PLAYER,
+            arg("duration", ArgKind::Duration),
+            opt("reason", ArgKind::Rest),
+        ],
+        rank: Rank::Moderator,
+        runner: Runner::Anyone,
+        help: "Bans a player by name for a while, e.g. 30m, 12h or 7d",
+        build: |a| Some(Command::TempBan(a.text(), a.duration(), a.opt_text())),
+    },
+    CommandInfo {
+        name: "banip",
+        aliases: &[],
+        args: &[arg("address", ArgKind::Word), opt("reason", ArgKind::Rest)],
+        rank: Rank::Moderator,
+        runner: Runner::Anyone,
+        help: "Bans an IP address, a range like 10.0.0.0/8 or the address of an online player",
+        build: |a| Some(Command::BanIp(a.text(), a.opt_text())),
+    },
+    CommandInfo {
+        name: "unban",
+        aliases: &[],
+        args: &[arg("target", ArgKind::Word)],
+        rank: Rank::Moderator,
+        runner: Runner::Anyone,
+        help: "Lifts the ban of a name, address or range",
+        build: |a| Some(Command::Unban(a.text())),
+    },
+    CommandInfo {
+        name: "whitelist",
+        aliases: &[],
+        args: &[
+            arg(
+                "action",
+                ArgKind::Choice(&["add", "remove", "list", "on", "off"]),
+            ),
+            OPT_PLAYER,
+        ],
+        rank: Rank::Admin,
+        runner: Runner::Anyone,
+        help: "Changes who can join while the whitelist is on",
+        build: |a| {
+            let action = match (a.text().as_str(), a.opt_text()) {
+                ("add", Some(name)) => WhitelistAction::Add(name),
+                ("remove", Some(name)) => WhitelistAction::Remove(name),
+                ("list", None) => WhitelistAction::List,
+                ("on", None) => WhitelistAction::On,
+                ("off", None) => WhitelistAction::Off,
+                _ => return None,
+            };
+            Some(Command::Whitelist(action))
+        },
+    },
+    CommandInfo {
+        name: "msg",
+        aliases: &["tell", "whisper"],
+        args: &[PLAYER, arg("message", ArgKind::Rest)],
+        rank: Rank::Guest,
+        runner: Runner::Anyone,
+        help: "Sends a private message to a player",
+        build: |a| Some(Command::Msg(a.text(), a.text())),
+    },
+    CommandInfo {
+        name: "r",
+        aliases: &["reply"],
+        args: &[arg("message", ArgKind::Rest)],
+        rank: Rank::Guest,
+        runner: Runner::Player,
+        help: "Answers the last private message you got",
+        build: |a| Some(Command::Reply(a.text())),
+    },
+    CommandInfo {
+        name: "me",
+        aliases: &[],
+        args: &[arg("action", ArgKind::Rest)],
+        rank: Rank::Guest,
+        runner: Runner::Anyone,
+        help: "Describes what you are doing in the chat",
+        build: |a| Some(Command::Me(a.text())),
+    },
+    CommandInfo {
+        name: "who",
+        aliases: &["online", "players"],
+        args: &[],
+        rank: Rank::Guest,
+        runner: Runner::Anyone,
+        help: "Lists the online players with their world and rank",
+        build: |_| Some(Command::Who),
+    },
+    CommandInfo {
+        name: "seen",
+        aliases: &[],
+        args: &[PLAYER],
+        rank: Rank::Guest,
+        runner: Runner::Anyone,
+        help: "Tells when a player was last online",
+        build: |a| Some(Command::Seen(a.text())),
+    },
+    CommandInfo {
+        name: "kick",
+        aliases: &[],
+        args: &[PLAYER, opt("reason", ArgKind::Rest)],
+        rank: Rank::Moderator,
+        runner: Runner::Anyone,
+        help: "Disconnects a player",
+        build: |a| Some(Command::Kick(a.text(), a.opt_text())),
+    },
+    CommandInfo {
+        name: "mute",
+        aliases: &[],
+        args: &[PLAYER, arg("duration", ArgKind::Duration)],
+        rank: Rank::Moderator,
+        runner: Runner::Anyone,
+        help: "Stops a player from chatting for a while, e.g. 10m",
+        build: |a| Some(Command::Mute(a.text(), a.duration())),
+    },
+    CommandInfo {
+        name: "unmute",
+        aliases: &[],
+        args: &[PLAYER],
+        rank: Rank::Moderator,
+        runner: Runner::Anyone,
+        help: "Lets a muted player chat again",
+        build: |a| Some(Command::Unmute(a.text())),
+    },
+    CommandInfo {
+        name: "freeze",
+        aliases: &[],
+        args: &[PLAYER],
+        rank: Rank::Moderator,
+        runner: Runner::Anyone,
+        help: "Stops a player from moving, or lets them move again",
+        build: |a| Some(Command::Freeze(a.text())),
+    },
+    CommandInfo {
+        name: "summon",
+        aliases: &["tphere"],
+        args: &[arg("player|*", ArgKind::Word)],
+        rank: Rank::Moderator,
+        runner: Runner::Player,
+        help: "Teleports a player, or everyone with *, to you",
+        build: |a| {
+            let target = a.text();
+            let target = match target.as_str() {
+                "*" => None,
+                name if login::is_valid_name(name) => Some(target),
+                _ => return None,
+            };
+            Some(Command::Summon(target))
+        },
+    },
+    CommandInfo {
+        name: "spawn",
+        aliases: &[],
+        args: &[],
+        rank: Rank::Guest,
+        runner: Runner::Player,
+        help: "Teleports you to the spawn point of the world",
+        build: |_| Some(Command::Spawn),
+    },
+    CommandInfo {
+        name: "setspawn",
+        aliases: &[],
+        args: &[],
+        rank: Rank::Admin,
+        runner: Runner::Player,
+        help: "Makes your position the spawn point of the world",
+        build: |_| Some(Command::SetSpawn),
+    },
+    CommandInfo {
+        name: "home",
+        aliases: &[],
+        args: &[],
+        rank: Rank::Guest,
+        runner: Runner::Player,
+        help: "Teleports you to your home",
+        build: |_| Some(Command::Home),
+    },
+    CommandInfo {
+        name: "sethome",
+        aliases: &[],
+        args: &[],
+        rank: Rank::Guest,
+        runner: Runner::Player,
+        help: "Makes your position your home",
+        build: |_| Some(Command::SetHome),
+    },
+    CommandInfo {
+        name: "warp",
+        aliases: &["warps"],
+        args: &[opt("name", ArgKind::Word)],
+        rank: Rank::Guest,
+        runner: Runner::Player,
+        help: "Teleports you to a warp or lists all warps",
+        build: |a| Some(Command::Warp(a.opt_text())),
+    },
+    CommandInfo {
+        name: "setwarp",
+        aliases: &[],
+        args: &[arg("name", ArgKind::Word)],
+        rank: Rank::Moderator,
+        runner: Runner::Player,
+        help: "Creates or moves a warp to your position",
+        build: |a| Some(Command::SetWarp(a.text())),
+    },
+    CommandInfo {
+        name: "delwarp",
+        aliases: &[],
+        args: &[arg("name", ArgKind::Word)],
+        rank: Rank::Moderator,
+        runner: Runner::Anyone,
+        help: "Removes a warp",
+        build: |a| Some(Command::DelWarp(a.text())),
+    },
+    CommandInfo {
+        name: "stop",
+        aliases: &[],
+        args: &[],
+        rank: Rank::Owner,
+        runner: Runner::Console,
+        help: "Saves the map and shuts the server down",
+        build: |_| Some(Command::Stop),
+    },
+    CommandInfo {
+        name: "save",
+        aliases: &[],
+        args: &[],
+        rank: Rank::Owner,
+        runner: Runner::Console,
+        help: "Saves the map right away",
+        build: |_| Some(Command::Save),
+    },
+    CommandInfo {
+        name: "say",
+        aliases: &[],
+        args: &[arg("message", ArgKind::Rest)],
+        rank: Rank::Owner,
+        runner: Runner::Console,
+        help: "Announces a message to every player",
+        build: |a| Some(Command::Say(a.text())),
+    },
+    CommandInfo {
+        name: "reload",
+        aliases: &[],
+        args: &[],
+        rank: Rank::Admin,
+        runner: Runner::Anyone,
+        help: "Applies changes of config.toml which do not need a restart",
+        build: |_| Some(Command::Reload),
+    },
+];
+
+/// Finds a command by its name or one of its aliases.
+pub fn find(name: &str) -> Option<&'static CommandInfo> {
+    COMMANDS.iter().find(|c| {
+        c.name.eq_ignore_ascii_case(name) || c.aliases.iter().any(|a| a.eq_ignore_ascii_case(name))
+    })
+}
+
+/// Splits at spaces while keeping text in double quotes together, returns where each word
+/// starts as well.
+fn tokenize(s: &str) -> Vec<(usize, String)> {
+    let mut words = vec![];
+    let mut chars = s.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        if c == ' ' {
+            continue;
+        }
+        let mut word = String::new();
+        if c == '"' {
+            for (_, c) in chars.by_ref() {
+                if c == '"' {
+                    break;
                 }
-                _ => Err(CommandError::CommandNotKnown),
+                word.push(c);
             }
         } else {
-            Err(CommandError::NoCommand)
+            word.push(c);
+            while let Some((_, c)) = chars.next_if(|(_, c)| *c != ' ') {
+                word.push(c);
+            }
         }
+        words.push((start, word));
     }
+    words
+}
+
+/// Parses a command without the leading `/`, block arguments can not exceed `max_block`.
+pub fn parse(s: &str, max_block: u16) -> Result<(&'static CommandInfo, Command), CommandError> {
+    let words = tokenize(s);
+    let Some((_, name)) = words.first() else {
+        return Err(CommandError::NoCommand);
+    };
+    let info = find(name).ok_or_else(|| CommandError::CommandNotKnown(name.clone()))?;
+
+    let mut words = words[1..].iter();
+    let mut values = vec![];
+    for arg in info.args {
+        let value = match (arg.kind, words.next()) {
+            (ArgKind::Rest, Some((start, _))) => {
+                let rest = s[*start..].trim();
+                let rest = rest
+                    .strip_prefix('"')
+                    .and_then(|r| r.strip_suffix('"'))
+                    .unwrap_or(rest);
+                words.by_ref().for_each(drop);
+                Some(Value::Text(rest.to_string()))
+            }
+            (kind, Some((_, word))) => Some(
+                kind.parse(word, max_block)
+                    .ok_or(CommandError::InvalidArgument(info, arg))?,
+            ),
+            (_, None) if arg.optional => None,
+            (_, None) => return Err(CommandError::NotEnoughArguments(info)),
+        };
+        values.push(value);
+    }
+    if words.next().is_some() {
+        return Err(CommandError::TooManyArguments(info));
+    }
+
+    (info.build)(&mut Args(values.into_iter()))
+        .map(|cmd| (info, cmd))
+        .ok_or(CommandError::InvalidArguments(info))
+}
+
+/// Splits `text` at spaces into chat lines which all start with `color`.
+pub fn wrap(color: &str, text: &str) -> Vec<String> {
+    let mut lines = vec![];
+    let mut line = color.to_string();
+    for word in text.split(' ') {
+        if line.len() > color.len() && line.len() + 1 + word.len() > 64 {
+            lines.push(line);
+            line = color.to_string();
+        }
+        if line.len() > color.len() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    lines.push(line);
+    lines
+}
+
+/// Parses durations like `30s`, `10m`, `2h`, `3d` or `1w`.
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    let unit = match s.chars().last()? {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        'w' => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    let count: u64 = s[..s.len() - 1].parse().ok()?;
+    Some(Duration::from_secs(count.checked_mul(unit)?)).filter(|d| !d.is_zero())
+}
+
+/// Formats a duration in the units accepted by [`parse_duration`], e.g. `1d 2h 5m`.
+pub fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    if secs < 60 {
+        return format!("{secs}s");
+    }
+    [
+        (secs / 86400, 'd'),
+        (secs / 3600 % 24, 'h'),
+        (secs / 60 % 60, 'm'),
+    ]
+    .into_iter()
+    .filter(|(n, _)| *n > 0)
+    .map(|(n, unit)| format!("{n}{unit}"))
+    .collect::<Vec<_>>()
+    .join(" ")
 }
 
 pub enum CommandError {
     NoCommand,
-    CommandNotKnown,
-    TooManyArguments,
-    NotEnoughArguments,
+    CommandNotKnown(String),
+    TooManyArguments(&'static CommandInfo),
+    NotEnoughArguments(&'static CommandInfo),
+    InvalidArgument(&'static CommandInfo, &'static Arg),
+    /// The arguments are valid on their own but not together
+    InvalidArguments(&'static CommandInfo),
+}
+
+impl Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoCommand => write!(f, "Enter a command after the /, see /help"),
+            Self::CommandNotKnown(name) => write!(f, "/{name} is not known, see /help"),
+            Self::TooManyArguments(info) => {
+                write!(f, "Too many arguments, usage: {}", info.usage())
+            }
+            Self::NotEnoughArguments(info) => {
+                write!(f, "Missing arguments, usage: {}", info.usage())
+            }
+            Self::InvalidArgument(info, arg) => {
+                write!(f, "Invalid {arg}, usage: {}", info.usage())
+            }
+            Self::InvalidArguments(info) => {
+                write!(f, "Invalid arguments, usage: {}", info.usage())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(s: &str) -> Command {
+        match parse(s, blocks::MAX_EXTENDED_ID) {
+            Ok((_, cmd)) => cmd,
+            Err(e) => panic!("/{s} was rejected: {e}"),
+        }
+    }
+
+    fn error(s: &str) -> CommandError {
+        match parse(s, blocks::MAX_EXTENDED_ID) {
+            Ok((info, _)) => panic!("/{s} was parsed as /{}", info.name),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn optional_arguments_come_last() {
+        for info in COMMANDS {
+            let first_optional = info.args.iter().position(|a| a.optional);
+            if let Some(first) = first_optional {
+                assert!(
+                    info.args[first..].iter().all(|a| a.optional),
+                    "/{} has a required argument after an optional one",
+                    info.name
+                );
+            }
+            let rest = info.args.iter().position(|a| a.kind == ArgKind::Rest);
+            if let Some(rest) = rest {
+                assert_eq!(
+                    rest,
+                    info.args.len() - 1,
+                    "/{} continues after Rest",
+                    info.name
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn quoted_words_stay_together() {
+        let words: Vec<_> = tokenize(r#"a "b c"  d"#)
+            .into_iter()
+            .map(|(_, w)| w)
+            .collect();
+        assert_eq!(words, ["a", "b c", "d"]);
+    }
+
+    #[test]
+    fn rest_keeps_everything_that_follows() {
+        assert!(matches!(
+            command("msg bob hello  there \"friend\""),
+            Command::Msg(name, message) if name == "bob" && message == "hello  there \"friend\""
+        ));
+        assert!(matches!(
+            command(r#"say "hello there""#),
+            Command::Say(message) if message == "hello there"
+        ));
+    }
+
+    #[test]
+    fn optional_arguments_can_be_left_out() {
+        assert!(matches!(command("kick bob"), Command::Kick(_, None)));
+        assert!(matches!(
+            command("kick bob go away"),
+            Command::Kick(_, Some(reason)) if reason == "go away"
+        ));
+        assert!(matches!(command("warp"), Command::Warp(None)));
+    }
+
+    #[test]
+    fn argument_count_is_checked() {
+        assert!(matches!(
+            error("msg bob"),
+            CommandError::NotEnoughArguments(_)
+        ));
+        assert!(matches!(
+            error("hotbar 1"),
+            CommandError::NotEnoughArguments(_)
+        ));
+        assert!(matches!(
+            error("save now"),
+            CommandError::TooManyArguments(_)
+        ));
+        assert!(matches!(
+            error("warp a b"),
+            CommandError::TooManyArguments(_)
+        ));
+        assert!(matches!(error(""), CommandError::NoCommand));
+        assert!(matches!(error("nope"), CommandError::CommandNotKnown(_)));
+    }
+
+    #[test]
+    fn coordinates_can_be_relative() {
+        assert!(matches!(
+            command("tp ~ ~5 -3"),
+            Command::Tp(TpTarget::Position(
+                Coordinate::Relative(0),
+                Coordinate::Relative(5),
+                Coordinate::Absolute(-3)
+            ))
+        ));
+        assert_eq!(Coordinate::Relative(-2).resolve(10), 8);
+        assert!(matches!(
+            command("tp bob"),
+            Command::Tp(TpTarget::Player(_))
+        ));
+        assert!(matches!(error("tp 1 2"), CommandError::InvalidArguments(_)));
+        assert!(matches!(
+            error("tp 1 ~x 2"),
+            CommandError::InvalidArgument(..)
+        ));
+    }
+
+    #[test]
+    fn blocks_are_ids_or_names() {
+        assert!(matches!(command("hotbar 1 stone"), Command::Hotbar(1, 1)));
+        assert!(matches!(command("hotbar 9 STONE"), Command::Hotbar(9, 1)));
+        assert!(matches!(command("hotbar 2 20"), Command::Hotbar(2, 20)));
+        assert!(matches!(
+            error("hotbar 1 nothing"),
+            CommandError::InvalidArgument(..)
+        ));
+        assert!(matches!(
+            error("hotbar 10 stone"),
+            CommandError::InvalidArgument(..)
+        ));
+    }
+
+    #[test]
+    fn blocks_are_limited_to_what_the_client_knows() {
+        assert!(matches!(command("fill 767"), Command::Fill(767)));
+        assert!(matches!(
+            error("fill 768"),
+            CommandError::InvalidArgument(..)
+        ));
+        assert!(matches!(
+            parse("fill 255", blocks::MAX_ID),
+            Ok((_, Command::Fill(255)))
+        ));
+        assert!(matches!(
+            parse("fill 256", blocks::MAX_ID),
+            Err(CommandError::InvalidArgument(..))
+        ));
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("30s"), Some(Duration::from_secs(30)));
+        assert_eq!(parse_duration("2h"), Some(Duration::from_secs(2 * 60 * 60)));
+        for bad in [
+            "",
+            "10",
+            "m",
+            "0m",
+            "-5m",
+            "1.5h",
+            "10y",
+            "99999999999999999w",
+        ] {
+            assert_eq!(parse_duration(bad), None, "{bad}");
+        }
+        assert!(matches!(
+            error("mute bob forever"),
+            CommandError::InvalidArgument(..)
+        ));
+        assert_eq!(
+            format_duration(Duration::from_secs(26 * 60 * 60 + 5 * 60)),
+            "1d 2h 5m"
+        );
+    }
 }
@@ -0,0 +1,255 @@
+/* This file is part of classicl-server.
+ *
+ * classicl-server is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use clap::{parser::ValueSource, CommandFactory, FromArgMatches};
+use serde::Deserialize;
+
+use crate::{cli::Cli, login::DuplicateLogin, ranks::Rank};
+
+/// Location of the configuration file inside the data directory
+pub fn path(data: &Path) -> PathBuf {
+    data.join("config.toml")
+}
+
+/// Contents of `config.toml`, settings which are left out keep the defaults of the command line
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Config {
+    server: ServerConfig,
+    world: WorldConfig,
+    ranks: RanksConfig,
+    autosave: AutosaveConfig,
+    chat: ChatConfig,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ServerConfig {
+    address: Option<String>,
+    name: Option<String>,
+    motd: Option<String>,
+    limit: Option<i8>,
+    heartbeat_url: Option<String>,
+    public: Option<bool>,
+    duplicate_login: Option<DuplicateLogin>,
+    rcon_address: Option<String>,
+    api_address: Option<String>,
+    metrics_address: Option<String>,
+}
+
+/// Used when the map is generated
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct WorldConfig {
+    x_size: Option<i16>,
+    y_size: Option<i16>,
+    z_size: Option<i16>,
+    terrain_height: Option<f64>,
+    water_height: Option<i16>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RanksConfig {
+    default: Option<Rank>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct AutosaveConfig {
+    interval: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ChatConfig {
+    full_message: Option<String>,
+    join_message: Option<String>,
+    leave_message: Option<String>,
+}
+
+/// Replaces `value` unless the flag was given on the command line.
+fn set<T>(value: &mut T, from_file: Option<T>, given: bool) {
+    if let (Some(v), false) = (from_file, given) {
+        *value = v;
+    }
+}
+
+/// The command line as it was given, kept to read `config.toml` again on `/reload`
+pub struct Loader {
+    cli: Cli,
+    /// Ids of the flags given on the command line
+    given: Vec<String>,
+}
+
+impl Loader {
+    pub fn parse() -> Self {
+        let matches = Cli::command().get_matches();
+        let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+        let given = matches
+            .ids()
+            .filter(|id| matches.value_source(id.as_str()) == Some(ValueSource::CommandLine))
+            .map(|id| id.to_string())
+            .collect();
+        Self { cli, given }
+    }
+
+    /// Reads `config.toml` inside the data directory, flags given on the command line take
+    /// precedence over it.
+    pub fn load(&self) -> Result<Cli, String> {
+        let path = path(&self.cli.data);
+        let config: Config = match fs::read_to_string(&path) {
+            Ok(s) => toml::from_str(&s).map_err(|e| format!("{}: {e}", path.display()))?,
+            Err(_) => Config::default(),
+        };
+        let given = |id: &str| self.given.iter().any(|g| g == id);
+
+        let mut cli = self.cli.clone();
+        let server = config.server;
+        set(&mut cli.address, server.address, given("address"));
+        set(&mut cli.name, server.name, given("name"));
+        set(&mut cli.motd, server.motd, given("motd"));
+        set(&mut cli.limit, server.limit.map(Some), given("limit"));
+        let heartbeat_url = server.heartbeat_url.map(Some);
+        set(
+            &mut cli.heartbeat_url,
+            heartbeat_url,
+            given("heartbeat_url"),
+        );
+        set(&mut cli.public, server.public, given("public"));
+        let duplicate_login = server.duplicate_login;
+        set(
+            &mut cli.duplicate_login,
+            duplicate_login,
+            given("duplicate_login"),
+        );
+        let rcon_address = server.rcon_address.map(Some);
+        set(&mut cli.rcon_address, rcon_address, given("rcon_address"));
+        let api_address = server.api_address.map(Some);
+        set(&mut cli.api_address, api_address, given("api_address"));
+        let metrics_address = server.metrics_address.map(Some);
+        set(
+            &mut cli.metrics_address,
+            metrics_address,
+            given("metrics_address"),
+        );
+
+        let world = config.world;
+        set(&mut cli.x_size, world.x_size, given("x_size"));
+        set(&mut cli.y_size, world.y_size, given("y_size"));
+        set(&mut cli.z_size, world.z_size, given("z_size"));
+        set(
+            &mut cli.terrain_height,
+            world.terrain_height,
+            given("terrain_height"),
+        );
+        set(
+            &mut cli.water_height,
+            world.water_height,
+            given("water_height"),
+        );
+        if cli.x_size < 1 || cli.y_size < 1 || cli.z_size < 1 {
+            return Err(format!("{}: sizes must be at least 1", path.display()));
+        }
+
+        set(
+            &mut cli.default_rank,
+            config.ranks.default,
+            given("default_rank"),
+        );
+        set(
+            &mut cli.autosave,
+            config.autosave.interval,
+            given("autosave"),
+        );
+
+        let chat = config.chat;
+        set(
+            &mut cli.full_message,
+            chat.full_message,
+            given("full_message"),
+        );
+        let join_message = chat.join_message.map(Some);
+        set(&mut cli.join_message, join_message, given("join_message"));
+        let leave_message = chat.leave_message.map(Some);
+        set(
+            &mut cli.leave_message,
+            leave_message,
+            given("leave_message"),
+        );
+        Ok(cli)
+    }
+}
+
+/// Settings in effect, `/reload` replaces the ones which can change while the server runs
+#[derive(Clone)]
+pub struct Live(Arc<RwLock<Arc<Cli>>>);
+
+impl Live {
+    pub fn new(cli: Cli) -> Self {
+        Self(Arc::new(RwLock::new(Arc::new(cli))))
+    }
+
+    pub fn get(&self) -> Arc<Cli> {
+        self.0.read().unwrap().clone()
+    }
+
+    /// Loads the settings again and applies the ones which can change while the server runs.
+    ///
+    /// Returns the names of the changed settings which only apply after a restart.
+    pub fn reload(&self, loader: &Loader) -> Result<Vec<&'static str>, String> {
+        let mut new = loader.load()?;
+        let old = self.get();
+        let restart = [
+            ("address", new.address != old.address),
+            ("x_size", new.x_size != old.x_size),
+            ("y_size", new.y_size != old.y_size),
+            ("z_size", new.z_size != old.z_size),
+            ("terrain_height", new.terrain_height != old.terrain_height),
+            ("water_height", new.water_height != old.water_height),
+            ("heartbeat_url", new.heartbeat_url != old.heartbeat_url),
+            ("rcon_address", new.rcon_address != old.rcon_address),
+            ("api_address", new.api_address != old.api_address),
+            (
+                "metrics_address",
+                new.metrics_address != old.metrics_address,
+            ),
+            // Players who are online keep the rank they joined with
+            ("default_rank", new.default_rank != old.default_rank),
+        ]
+        .into_iter()
+        .filter(|(_, changed)| *changed)
+        .map(|(name, _)| name)
+        .collect();
+        new.address.clone_from(&old.address);
+        (new.x_size, new.y_size, new.z_size) = (old.x_size, old.y_size, old.z_size);
+        new.terrain_height = old.terrain_height;
+        new.water_height = old.water_height;
+        new.heartbeat_url.clone_from(&old.heartbeat_url);
+        new.rcon_address.clone_from(&old.rcon_address);
+        new.api_address.clone_from(&old.api_address);
+        new.metrics_address.clone_from(&old.metrics_address);
+        new.default_rank = old.default_rank;
+        *self.0.write().unwrap() = Arc::new(new);
+        Ok(restart)
+    }
+}
@@ -0,0 +1,67 @@
+/* This file is part of classicl-server.
+ *
+ * classicl-server is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{io::BufRead, thread};
+
+use tokio::sync::mpsc;
+
+use crate::{execute, Sender, State};
+
+/// Name shown to players for anything done from the console
+pub const NAME: &str = "Console";
+
+/// Runs every line written to the standard input as a command, with or without the leading `/`.
+///
+/// Replies are printed to the standard output without color codes.
+pub async fn run(state: State) {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    // Blocking reads on the async stdin of tokio would keep the runtime from shutting down
+    thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    while let Some(line) = rx.recv().await {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let mut sender = Sender::console(NAME);
+        execute(&mut sender, line.strip_prefix('/').unwrap_or(line), &state).await;
+        for reply in sender.take_lines() {
+            println!("{}", strip_colors(&reply));
+        }
+    }
+}
+
+/// Removes color codes like `&e` from a message.
+pub fn strip_colors(message: &str) -> String {
+    let mut stripped = String::with_capacity(message.len());
+    let mut chars = message.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '&' && chars.peek().is_some_and(|c| c.is_ascii_hexdigit()) {
+            chars.next();
+        } else {
+            stripped.push(c);
+        }
+    }
+    stripped
+}
@@ -0,0 +1,140 @@
+/* This file is part of classicl-server.
+ *
+ * classicl-server is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::net::IpAddr;
+
+use classicl::{client::ExtEntry, server, ClientController, Packet};
+use tokio::sync::oneshot;
+
+use crate::ranks::Rank;
+
+/// Sent as [`classicl::client::PlayerIdentification::unused`] by clients supporting CPE
+pub const MAGIC: u8 = 0x42;
+
+const APP_NAME: &str = concat!("classicl_server ", env!("CARGO_PKG_VERSION"));
+
+pub const HACK_CONTROL: &str = "HackControl";
+pub const SELECTION_CUBOID: &str = "SelectionCuboid";
+pub const PLAYER_CLICK: &str = "PlayerClick";
+pub const HELD_BLOCK: &str = "HeldBlock";
+pub const FAST_MAP: &str = "FastMap";
+pub const EXTENDED_BLOCKS: &str = "ExtendedBlocks";
+pub const EXT_ENTITY_POSITIONS: &str = "ExtEntityPositions";
+pub const CHANGE_MODEL: &str = "ChangeModel";
+pub const ENTITY_PROPERTY: &str = "EntityProperty";
+pub const TWO_WAY_PING: &str = "TwoWayPing";
+pub const EXT_PLAYER_LIST: &str = "ExtPlayerList";
+pub const SET_SPAWNPOINT: &str = "SetSpawnpoint";
+pub const VELOCITY_CONTROL: &str = "VelocityControl";
+pub const SET_HOTBAR: &str = "SetHotbar";
+pub const TEXT_HOT_KEY: &str = "TextHotKey";
+pub const INSTANT_MOTD: &str = "InstantMOTD";
+pub const CINEMATIC_GUI: &str = "CinematicGui";
+pub const CUSTOM_PARTICLES: &str = "CustomParticles";
+pub const CUSTOM_MODELS: &str = "CustomModels";
+pub const BULK_BLOCK_UPDATE: &str = "BulkBlockUpdate";
+
+/// Extensions supported by the server and their versions
+const EXTENSIONS: &[(&str, i32)] = &[
+    (HACK_CONTROL, 1),
+    (SELECTION_CUBOID, 1),
+    (PLAYER_CLICK, 1),
+    (HELD_BLOCK, 1),
+    (FAST_MAP, 1),
+    (EXTENDED_BLOCKS, 1),
+    (EXT_ENTITY_POSITIONS, 1),
+    (CHANGE_MODEL, 1),
+    (ENTITY_PROPERTY, 1),
+    (TWO_WAY_PING, 1),
+    (EXT_PLAYER_LIST, 2),
+    (SET_SPAWNPOINT, 1),
+    (VELOCITY_CONTROL, 1),
+    (SET_HOTBAR, 1),
+    (TEXT_HOT_KEY, 1),
+    (INSTANT_MOTD, 1),
+    (CINEMATIC_GUI, 1),
+    (CUSTOM_PARTICLES, 1),
+    (CUSTOM_MODELS, 2),
+    (BULK_BLOCK_UPDATE, 1),
+];
+
+/// [`classicl::client::PlayerClick::action`] when a button was pressed
+pub const CLICK_PRESSED: u8 = 0;
+
+/// Extensions both the server and a client support
+#[derive(Clone, Debug, Default)]
+pub struct Extensions(Vec<&'static str>);
+
+impl Extensions {
+    pub fn from_entries(entries: &[ExtEntry]) -> Self {
+        Self(
+            EXTENSIONS
+                .iter()
+                .filter(|(name, version)| {
+                    entries
+                        .iter()
+                        .any(|e| e.ext_name.trim() == *name && e.version == *version)
+                })
+                .map(|(name, _)| *name)
+                .collect(),
+        )
+    }
+
+    pub fn has(&self, name: &str) -> bool {
+        self.0.contains(&name)
+    }
+}
+
+/// A client which announced CPE support but has not sent all of its extensions yet
+pub struct Negotiation {
+    pub c: ClientController,
+    pub tx: oneshot::Sender<()>,
+    pub username: String,
+    pub addr: IpAddr,
+    pub rank: Rank,
+    pub frozen: bool,
+    pub count: Option<usize>,
+    pub entries: Vec<ExtEntry>,
+}
+
+impl Negotiation {
+    pub fn is_complete(&self) -> bool {
+        matches!(self.count, Some(count) if self.entries.len() >= count)
+    }
+}
+
+/// Writes the servers ExtInfo followed by one ExtEntry per supported extension.
+pub async fn write_ext_info(c: &ClientController) {
+    let mut buf = vec![server::ExtInfo::ID];
+    buf.append(
+        &mut classicl::to_bytes(&server::ExtInfo {
+            app_name: APP_NAME.into(),
+            extension_count: EXTENSIONS.len() as i16,
+        })
+        .unwrap(),
+    );
+    for (name, version) in EXTENSIONS {
+        buf.push(server::ExtEntry::ID);
+        buf.append(
+            &mut classicl::to_bytes(&server::ExtEntry {
+                ext_name: name.to_string(),
+                version: *version,
+            })
+            .unwrap(),
+        );
+    }
+    let _ = c.write_bytes(buf).await;
+}
@@ -0,0 +1,187 @@
+/* This file is part of classicl-server.
+ *
+ * classicl-server is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{fmt::Display, time::Duration};
+
+use classicl::{client, server::HackControl};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    terrain::{blocks, Terrain},
+    Player,
+};
+
+/// Distance between the feet and the position sent by the client
+const EYE_HEIGHT: i32 = 51;
+/// Jump height of clients without any modifications
+const DEFAULT_JUMP_HEIGHT: i32 = 40;
+/// Furthest horizontal distance a player can walk between two packets
+const MAX_STEP: i32 = 48;
+/// Leeway for rounding errors
+const TOLERANCE: i32 = 16;
+/// Most additional leeway given to players with a high latency
+const MAX_LAG_TOLERANCE: i32 = 64;
+/// Time after VelocityControl during which the movement is not checked
+const LAUNCH_GRACE: Duration = Duration::from_secs(5);
+
+/// Movement hacks a client is allowed to use
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Hacks {
+    pub flying: bool,
+    pub no_clip: bool,
+    pub speeding: bool,
+    pub spawn_control: bool,
+    pub third_person_view: bool,
+    /// Jump height in blocks, clients use their default if none is given
+    pub jump_height: Option<f64>,
+}
+
+impl Default for Hacks {
+    fn default() -> Self {
+        Self {
+            flying: true,
+            no_clip: true,
+            speeding: true,
+            spawn_control: true,
+            third_person_view: true,
+            jump_height: None,
+        }
+    }
+}
+
+impl Hacks {
+    pub fn to_hack_control(self) -> HackControl {
+        HackControl {
+            flying: self.flying.into(),
+            no_clip: self.no_clip.into(),
+            speeding: self.speeding.into(),
+            spawn_control: self.spawn_control.into(),
+            third_person_view: self.third_person_view.into(),
+            jump_height: self.jump_height.map_or(-1, |h| (h * 32.0).round() as i16),
+        }
+    }
+
+    /// Flags appended to the MOTD for clients without HackControl
+    pub fn motd_flags(&self) -> String {
+        let mut flags = vec![];
+        if !self.flying {
+            flags.push("-fly".to_string());
+        }
+        if !self.no_clip {
+            flags.push("-noclip".to_string());
+        }
+        if !self.speeding {
+            flags.push("-speed".to_string());
+        }
+        if !self.spawn_control {
+            flags.push("-respawn".to_string());
+        }
+        if let Some(h) = self.jump_height {
+            flags.push(format!("jumpheight={h:.2}"));
+        }
+        flags.join(" ")
+    }
+
+    fn max_jump(&self) -> i32 {
+        self.jump_height
+            .map_or(DEFAULT_JUMP_HEIGHT, |h| (h * 32.0).round() as i32)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Violation {
+    Flying,
+    NoClip,
+    Speeding,
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Flying => "flying",
+            Self::NoClip => "noclip",
+            Self::Speeding => "speeding",
+        })
+    }
+}
+
+/// Checks whether the movement of `player` to `to` was possible with the hacks they are allowed
+/// to use.
+pub fn check(
+    player: &Player,
+    to: &client::ExtPositionOrientation,
+    terrain: &Terrain,
+) -> Option<Violation> {
+    let hacks = &player.hacks;
+    let (x, y, z) = (to.x, to.y, to.z);
+    let respawned = (x, y, z) == terrain.spawn_point || Some((x, y, z)) == player.spawnpoint;
+    if hacks.spawn_control && respawned {
+        return None;
+    }
+    // The server moved the player on purpose
+    if player.launched.is_some_and(|t| t.elapsed() < LAUNCH_GRACE) {
+        return None;
+    }
+
+    // Packets of lagging clients arrive in bursts after moving further than usual
+    let lag_tolerance = player.latency.rtt().map_or(0, |rtt| {
+        (rtt.as_millis() / 10).min(MAX_LAG_TOLERANCE as u128) as i32
+    });
+
+    if !hacks.speeding {
+        let (dx, dz) = (x - player.x, z - player.z);
+        let max_step = MAX_STEP + lag_tolerance;
+        if dx * dx + dz * dz > max_step * max_step {
+            return Some(Violation::Speeding);
+        }
+    }
+
+    if !hacks.flying
+        && y > player.ground_y + hacks.max_jump() + TOLERANCE + lag_tolerance
+        && !block_at(terrain, x, y - EYE_HEIGHT, z).is_some_and(blocks::is_climbable)
+    {
+        return Some(Violation::Flying);
+    }
+
+    if !hacks.no_clip {
+        let is_solid = |y| {
+            block_at(terrain, x, y, z)
+                .is_some_and(|b| !blocks::is_passable(b) && !blocks::is_partial(b))
+        };
+        if is_solid(y - EYE_HEIGHT + 2) || is_solid(y) {
+            return Some(Violation::NoClip);
+        }
+    }
+
+    None
+}
+
+/// Whether a player at the given position stands on a block or is able to swim or climb.
+pub fn is_grounded(terrain: &Terrain, x: i32, y: i32, z: i32) -> bool {
+    let feet = y - EYE_HEIGHT;
+    block_at(terrain, x, feet - 1, z).is_some_and(|b| !blocks::is_passable(b))
+        || block_at(terrain, x, feet, z).is_some_and(blocks::is_climbable)
+}
+
+fn block_at(terrain: &Terrain, x: i32, y: i32, z: i32) -> Option<u8> {
+    let block = terrain.get_block(
+        x.div_euclid(32).try_into().ok()?,
+        y.div_euclid(32).try_into().ok()?,
+        z.div_euclid(32).try_into().ok()?,
+    )?;
+    Some(block.try_into().unwrap_or(blocks::FALLBACK))
+}
@@ -0,0 +1,92 @@
+/* This file is part of classicl-server.
+ *
+ * classicl-server is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{collections::HashMap, net::SocketAddr, sync::Arc, time::Duration};
+
+use log::{debug, info, warn};
+use md5::{Digest, Md5};
+use rand::{distributions::Alphanumeric, Rng};
+use tokio::{sync::Mutex, time};
+
+use crate::{config::Live, Player};
+
+/// Time between two heartbeats
+const INTERVAL: Duration = Duration::from_secs(45);
+const SALT_LENGTH: usize = 16;
+pub const SOFTWARE: &str = concat!("classicl_server ", env!("CARGO_PKG_VERSION"));
+
+/// Generates the secret shared with the server list to verify names.
+pub fn generate_salt() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(SALT_LENGTH)
+        .map(char::from)
+        .collect()
+}
+
+/// Checks whether the server list issued `key` for `username`.
+pub fn verify(salt: &str, username: &str, key: &str) -> bool {
+    let hash = Md5::digest(format!("{salt}{username}"));
+    let expected: String = hash.iter().map(|b| format!("{b:02x}")).collect();
+    expected.eq_ignore_ascii_case(key.trim())
+}
+
+/// Announces the server to the server list at `url` until the server stops.
+pub async fn run(
+    url: String,
+    live: Live,
+    salt: Arc<String>,
+    players: Arc<Mutex<HashMap<i8, Player>>>,
+) {
+    let client = reqwest::Client::new();
+    let port = live
+        .get()
+        .address
+        .parse::<SocketAddr>()
+        .map_or(25565, |a| a.port());
+    let mut announced = false;
+    loop {
+        let cli = live.get();
+        let max = cli.limit.unwrap_or(i8::MAX);
+        let users = players.lock().await.len();
+        let params = [
+            ("name", cli.name.clone()),
+            ("port", port.to_string()),
+            ("users", users.to_string()),
+            ("max", max.to_string()),
+            ("public", if cli.public { "True" } else { "False" }.into()),
+            ("salt", salt.to_string()),
+            ("version", "7".into()),
+            ("software", SOFTWARE.into()),
+        ];
+        match client.post(&url).form(&params).send().await {
+            Ok(response) => {
+                let status = response.status();
+                let body = response.text().await.unwrap_or_default();
+                if !status.is_success() {
+                    warn!("Heartbeat was rejected with {status}: {}", body.trim());
+                } else if !announced {
+                    announced = true;
+                    info!("Server list entry: {}", body.trim());
+                } else {
+                    debug!("Heartbeat sent.");
+                }
+            }
+            Err(e) => warn!("Could not send heartbeat: {e}"),
+        }
+        time::sleep(INTERVAL).await;
+    }
+}
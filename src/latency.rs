@@ -0,0 +1,72 @@
+/* This file is part of classicl-server.
+ *
+ * classicl-server is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::time::{Duration, Instant};
+
+use classicl::server::TwoWayPing;
+
+/// Time between two pings sent to a client
+pub const INTERVAL: Duration = Duration::from_secs(2);
+/// [`TwoWayPing::direction`] of pings started by a client
+pub const FROM_CLIENT: u8 = 0;
+/// [`TwoWayPing::direction`] of pings started by the server
+pub const FROM_SERVER: u8 = 1;
+
+/// Round-trip time measured with TwoWayPing
+#[derive(Clone, Debug, Default)]
+pub struct Latency {
+    pending: Option<(u16, Instant)>,
+    next: u16,
+    rtt: Option<Duration>,
+    /// Milliseconds last shown in the tab list
+    shown: Option<u128>,
+}
+
+impl Latency {
+    /// Starts a new measurement, discarding one which was not answered in time.
+    pub fn start(&mut self) -> TwoWayPing {
+        let data = self.next;
+        self.next = self.next.wrapping_add(1);
+        self.pending = Some((data, Instant::now()));
+        TwoWayPing {
+            direction: FROM_SERVER,
+            data,
+        }
+    }
+
+    /// Finishes the measurement answered by `data`, returns the smoothed round-trip time.
+    pub fn finish(&mut self, data: u16) -> Option<Duration> {
+        match self.pending {
+            Some((pending, sent)) if pending == data => {
+                self.pending = None;
+                let sample = sent.elapsed();
+                self.rtt = Some(self.rtt.map_or(sample, |rtt| (rtt * 3 + sample) / 4));
+                self.rtt
+            }
+            _ => None,
+        }
+    }
+
+    pub fn rtt(&self) -> Option<Duration> {
+        self.rtt
+    }
+
+    /// Remembers the milliseconds shown in the tab list, returns whether they changed.
+    pub fn update_shown(&mut self) -> bool {
+        let millis = self.rtt.map(|rtt| rtt.as_millis());
+        std::mem::replace(&mut self.shown, millis) != millis
+    }
+}
@@ -0,0 +1,113 @@
+/* This file is part of classicl-server.
+ *
+ * classicl-server is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::Player;
+
+/// Location of the homes and warps of the world inside the data directory
+///
+/// A data directory holds a single world. Like `mapdata` and `world.toml`, this file belongs to
+/// that world and needs no world name.
+pub fn path(data: &Path) -> PathBuf {
+    data.join("locations.toml")
+}
+
+/// Position and orientation in player units, like [`Player::x`]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Location {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+    pub yaw: u8,
+    pub pitch: u8,
+}
+
+impl Location {
+    pub fn of(p: &Player) -> Self {
+        Self {
+            x: p.x,
+            y: p.y,
+            z: p.z,
+            yaw: p.yaw,
+            pitch: p.pitch,
+        }
+    }
+}
+
+/// Homes of players and public warps of the world, saved in `locations.toml` inside the data
+/// directory
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Locations {
+    #[serde(skip)]
+    path: PathBuf,
+    /// Homes by lowercase player name
+    homes: BTreeMap<String, Location>,
+    /// Warps by lowercase name
+    warps: BTreeMap<String, Location>,
+}
+
+impl Locations {
+    pub fn load(path: &Path) -> Self {
+        let mut locations: Self = if let Ok(s) = fs::read_to_string(path) {
+            toml::from_str(&s).unwrap()
+        } else {
+            Self::default()
+        };
+        locations.path = path.to_path_buf();
+        locations
+    }
+
+    fn save(&self) {
+        fs::write(&self.path, toml::to_string_pretty(self).unwrap()).unwrap();
+    }
+
+    pub fn home(&self, name: &str) -> Option<Location> {
+        self.homes.get(&name.to_lowercase()).copied()
+    }
+
+    pub fn set_home(&mut self, name: &str, location: Location) {
+        self.homes.insert(name.to_lowercase(), location);
+        self.save();
+    }
+
+    pub fn warp(&self, name: &str) -> Option<Location> {
+        self.warps.get(&name.to_lowercase()).copied()
+    }
+
+    pub fn warps(&self) -> impl Iterator<Item = &String> {
+        self.warps.keys()
+    }
+
+    pub fn set_warp(&mut self, name: &str, location: Location) {
+        self.warps.insert(name.to_lowercase(), location);
+        self.save();
+    }
+
+    /// Removes a warp, returns whether it existed.
+    pub fn remove_warp(&mut self, name: &str) -> bool {
+        let removed = self.warps.remove(&name.to_lowercase()).is_some();
+        self.save();
+        removed
+    }
+}
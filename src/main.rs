@@ -14,30 +14,76 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use classicl::{client, server::*, ClientController, Packet};
-use log::{debug, info, LevelFilter};
+use classicl::{client, server::*, stats::STATS, ClientController, Packet, Serialize};
+use log::{debug, error, info, warn, LevelFilter};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fs::File,
     io::Write,
+    net::IpAddr,
     path::{Path, PathBuf},
     sync::Arc,
-    time::Duration,
+    time::{Duration, Instant},
 };
 use tokio::{
     select,
-    sync::{oneshot, Mutex},
+    sync::{oneshot, Mutex, Notify},
     time,
 };
 
-use crate::{cli::Cli, commands::Command, terrain::Terrain};
-use clap::Parser;
+use crate::{
+    assets::Assets,
+    bans::{Ban, Bans},
+    batch::BlockChanges,
+    cli::Cli,
+    commands::{Command, TpTarget, WhitelistAction},
+    cpe::{Extensions, Negotiation},
+    hacks::Hacks,
+    latency::Latency,
+    locations::{Location, Locations},
+    login::DuplicateLogin,
+    metrics::Handler,
+    model::Appearance,
+    ranks::{Permissions, Rank, Ranks},
+    seen::LastSeen,
+    selection::Selection,
+    terrain::{blocks, Terrain},
+    whitelist::Whitelist,
+    world::WorldSettings,
+};
 
+mod api;
+mod assets;
+mod bans;
+mod batch;
 mod cli;
 mod commands;
+mod config;
+mod console;
+mod cpe;
+mod hacks;
+mod heartbeat;
+mod latency;
+mod locations;
+mod login;
+mod metrics;
+mod model;
+mod ranks;
+mod rcon;
+mod secret;
+mod seen;
+mod selection;
 mod terrain;
+mod whitelist;
+mod world;
 
-const PLAYER_HEIGHT: i16 = 51 * 2;
+const PLAYER_HEIGHT: i32 = 51 * 2;
+/// Height of each cinematic bar as a fraction of [`u16::MAX`]
+const CINEMATIC_BAR_SIZE: u16 = u16::MAX / 8;
+/// Velocities are sent multiplied by this
+const VELOCITY_SCALE: f64 = 10000.0;
+/// Largest map dimension whose positions fit into the fixed-point coordinates of vanilla clients
+const MAX_VANILLA_SIZE: i16 = i16::MAX / 32;
 
 #[tokio::main]
 async fn main() {
@@ -48,7 +94,12 @@ async fn main() {
         .target(env_logger::Target::Stderr)
         .init();
 
-    let cli = Arc::new(Cli::parse());
+    let loader = config::Loader::parse();
+    let live = config::Live::new(loader.load().unwrap_or_else(|e| {
+        error!("Invalid configuration: {e}");
+        std::process::exit(1);
+    }));
+    let cli = live.get();
     std::fs::DirBuilder::new()
         .recursive(true)
         .create(&cli.data)
@@ -57,11 +108,12 @@ async fn main() {
 
     let pdb: Arc<Mutex<HashMap<i8, Player>>> = Arc::new(Mutex::new(HashMap::new()));
     let pq = Arc::new(Mutex::new(HashMap::new()));
+    let pn: Arc<Mutex<HashMap<i8, Negotiation>>> = Arc::new(Mutex::new(HashMap::new()));
 
     let path = generate_path(&cli.data);
-    let terrain = Arc::new(Mutex::new(if let Ok(file) = File::open(path) {
+    let terrain = Arc::new(Mutex::new(if let Ok(data) = std::fs::read(path) {
         info!("Loading Terrain...");
-        bincode::deserialize_from(file).unwrap()
+        Terrain::load(&data).unwrap()
     } else {
         info!("Generating Terrain...");
         Terrain::new(
@@ -73,37 +125,123 @@ async fn main() {
 
     info!("Terrain ready.");
 
+    let settings = Arc::new(Mutex::new(WorldSettings::load(&world::path(&cli.data))));
+    let mut ranks = Ranks::load(&ranks::path(&cli.data));
+    ranks.set_default(cli.default_rank);
+    let player_ranks = Arc::new(Mutex::new(ranks));
+    let rank_permissions = Arc::new(Permissions::load(&ranks::permissions_path(&cli.data)));
+    // Also read when accepting connections, which can not wait for an async lock
+    let ban_list = Arc::new(std::sync::Mutex::new(Bans::load(&bans::path(&cli.data))));
+    let name_whitelist = Arc::new(Mutex::new(Whitelist::load(&whitelist::path(&cli.data))));
+    let last_seen = Arc::new(Mutex::new(LastSeen::load(&seen::path(&cli.data))));
+    let world_locations = Arc::new(Mutex::new(Locations::load(&locations::path(&cli.data))));
+    // Ends of mutes by lowercase name, kept when muted players reconnect
+    let muted_until: Arc<Mutex<HashMap<String, Instant>>> = Arc::new(Mutex::new(HashMap::new()));
+    // Lowercase names of frozen players, kept when they reconnect
+    let frozen_names: Arc<Mutex<HashSet<String>>> = Arc::new(Mutex::new(HashSet::new()));
+    let world_assets = Arc::new(Assets::load(&assets::path(&cli.data)));
+
     let is_changed = Arc::new(Mutex::new(false));
+    let block_changes = Arc::new(Mutex::new(BlockChanges::default()));
+
+    let state = State {
+        cli: live.clone(),
+        loader: Arc::new(loader),
+        players: pdb.clone(),
+        map: terrain.clone(),
+        world: settings.clone(),
+        assets: world_assets.clone(),
+        changed: is_changed.clone(),
+        changes: block_changes.clone(),
+        ranks: player_ranks.clone(),
+        permissions: rank_permissions.clone(),
+        bans: ban_list.clone(),
+        whitelist: name_whitelist.clone(),
+        seen: last_seen.clone(),
+        mutes: muted_until.clone(),
+        frozen: frozen_names.clone(),
+        locations: world_locations.clone(),
+        stop: Arc::new(Notify::new()),
+        started: Instant::now(),
+    };
+    tokio::spawn(console::run(state.clone()));
+    if let Some(address) = cli.rcon_address.clone() {
+        let path = rcon::password_path(&cli.data);
+        match secret::load(&path) {
+            Ok(password) => {
+                tokio::spawn(rcon::run(address, password, state.clone()));
+            }
+            Err(e) => warn!("Not starting RCON, {} is unusable: {e}", path.display()),
+        }
+    }
+    if let Some(address) = cli.api_address.clone() {
+        let path = api::token_path(&cli.data);
+        match secret::load(&path) {
+            Ok(token) => {
+                tokio::spawn(api::run(address, token, state.clone()));
+            }
+            Err(e) => warn!(
+                "Not serving the HTTP API, {} is unusable: {e}",
+                path.display()
+            ),
+        }
+    }
+    if let Some(address) = cli.metrics_address.clone() {
+        tokio::spawn(api::run_metrics(address, state.clone()));
+    }
+
+    let players = pdb.clone();
+    let map = terrain.clone();
+    let changes = block_changes.clone();
+    tokio::spawn(async move {
+        loop {
+            time::sleep(batch::TICK).await;
+            batch::flush(&changes, &players, &map).await;
+        }
+    });
 
-    let opt = cli.clone();
+    let opt = live.clone();
     server
         .on_server_full(move || classicl::server::DisconnectPlayer {
-            disconnect_reason: format!("&cSorry, {} &cis full right now.", opt.name),
+            disconnect_reason: opt.get().full_message(),
         })
         .await;
 
+    let bans = ban_list.clone();
+    let opt = live.clone();
+    server
+        .on_client_accept(move |addr| {
+            if let Some(ban) = bans.lock().unwrap().ip(addr.ip()) {
+                return Some(DisconnectPlayer {
+                    disconnect_reason: ban.message(),
+                });
+            }
+            // Checked here instead of by the server so `/reload` can change it
+            let opt = opt.get();
+            let full = opt
+                .limit
+                .is_some_and(|limit| STATS.connected() >= limit.max(0) as u64);
+            full.then(|| DisconnectPlayer {
+                disconnect_reason: opt.full_message(),
+            })
+        })
+        .await;
+
+    let salt = Arc::new(heartbeat::generate_salt());
+    if let Some(url) = cli.heartbeat_url.clone() {
+        tokio::spawn(heartbeat::run(url, live.clone(), salt.clone(), pdb.clone()));
+    }
+
     let handler = server.on_client_connected().unwrap();
     let players = pq.clone();
-    let opt = cli.clone();
     tokio::spawn(async move {
         let mut handler = handler.await.unwrap();
         while let Some(data) = handler.get().await {
             let players = players.clone();
-            let opt = opt.clone();
             tokio::spawn(async move {
-                data.client
-                    .write_packet(&ServerIdentification {
-                        protocol_version: 0x07,
-                        server_name: opt.name.clone(),
-                        server_motd: opt.motd.clone(),
-                        user_type: 0x00,
-                    })
-                    .await
-                    .unwrap();
-
                 let mut players = players.lock().await;
                 let (tx, rx) = oneshot::channel();
-                players.insert(data.id, (data.client.clone(), tx));
+                players.insert(data.id, (data.client.clone(), tx, data.addr.ip()));
                 let c = data.client.clone();
                 tokio::spawn(async move {
                     select! {
@@ -122,58 +260,229 @@ async fn main() {
     let handler = server.on_player_identification().unwrap();
     let players = pdb.clone();
     let queue = pq.clone();
+    let negotiations = pn.clone();
     let map = terrain.clone();
+    let opt = live.clone();
+    let world = settings.clone();
+    let assets = world_assets.clone();
+    let salt = salt.clone();
+    let ranks = player_ranks.clone();
+    let bans = ban_list.clone();
+    let whitelist = name_whitelist.clone();
+    let frozen = frozen_names.clone();
+    let seen = last_seen.clone();
     tokio::spawn(async move {
         let mut handler = handler.await.unwrap();
         while let Some(data) = handler.get().await {
+            let seen = seen.clone();
+            let salt = salt.clone();
+            let ranks = ranks.clone();
+            let bans = bans.clone();
+            let whitelist = whitelist.clone();
+            let frozen = frozen.clone();
             let players = players.clone();
             let queue = queue.clone();
+            let negotiations = negotiations.clone();
             let map = map.clone();
+            let opt = opt.get();
+            let world = world.clone();
+            let assets = assets.clone();
             tokio::spawn(async move {
-                let mut players = players.lock().await;
-                if let Some((c, tx)) = queue.lock().await.remove(&data.id) {
-                    tx.send(()).unwrap();
-                    let spawn_point = map.lock().await.spawn_point;
-                    let player = Player {
-                        c: c.clone(),
-                        player_name: data.data.username.to_string(),
-                        x: spawn_point.0,
-                        y: spawn_point.1,
-                        z: spawn_point.2,
-                        yaw: 0,
-                        pitch: 0,
+                let _timer = metrics::time_handler(Handler::Identification);
+                metrics::IDENTIFICATIONS.add(1);
+                if let Some((c, tx, addr)) = queue.lock().await.remove(&data.id) {
+                    let username = data.data.username.trim();
+                    info!("{} identified as {username}", data.id);
+
+                    if !login::is_valid_name(username) {
+                        warn!("{} used an invalid name", data.id);
+                        c.disconnect(Some(&DisconnectPlayer {
+                            disconnect_reason: "Invalid name".into(),
+                        }))
+                        .await;
+                        return;
+                    }
+
+                    if opt.heartbeat_url.is_some()
+                        && !heartbeat::verify(
+                            &salt,
+                            data.data.username.trim(),
+                            &data.data.verification_key,
+                        )
+                    {
+                        warn!("{} could not be verified", data.id);
+                        c.disconnect(Some(&DisconnectPlayer {
+                            disconnect_reason: "Could not verify your name".into(),
+                        }))
+                        .await;
+                        return;
+                    }
+
+                    let ban = bans.lock().unwrap().name(username);
+                    if let Some(ban) = ban {
+                        info!("{username} is banned");
+                        c.disconnect(Some(&DisconnectPlayer {
+                            disconnect_reason: ban.message(),
+                        }))
+                        .await;
+                        return;
+                    }
+
+                    let message = {
+                        let whitelist = whitelist.lock().await;
+                        Some(whitelist.message.clone()).filter(|_| !whitelist.allows(username))
                     };
+                    if let Some(message) = message {
+                        info!("{username} is not whitelisted");
+                        c.disconnect(Some(&DisconnectPlayer {
+                            disconnect_reason: message,
+                        }))
+                        .await;
+                        return;
+                    }
 
-                    info!("{} identified as {}", data.id, data.data.username.trim());
+                    let rank = ranks.lock().await.get(username);
+                    let world = world.lock().await.clone();
+                    if rank < world.visit_rank {
+                        info!("{username} is not allowed to join the world");
+                        c.disconnect(Some(&DisconnectPlayer {
+                            disconnect_reason: "You are not allowed to join this world".into(),
+                        }))
+                        .await;
+                        return;
+                    }
+                    let frozen = frozen.lock().await.contains(&username.to_lowercase());
+                    let spawn_point = map.lock().await.spawn_point;
 
-                    let mut buf = vec![LevelInitialize::ID];
+                    // Held until the player is added, so two logins with the same name can not
+                    // both pass the check
+                    let mut players = players.lock().await;
+                    let mut negotiations = negotiations.lock().await;
+                    if !handle_duplicate_login(
+                        username,
+                        &mut players,
+                        &mut negotiations,
+                        &seen,
+                        &opt,
+                    )
+                    .await
                     {
-                        buf.append(&mut (classicl::to_bytes(&LevelInitialize {}).unwrap()));
-                        let map = map.lock().await;
-                        for i in map.to_chunks() {
-                            buf.push(LevelDataChunk::ID);
-                            buf.append(&mut classicl::to_bytes(i).unwrap());
-                        }
+                        info!("{} is already online, rejecting {}", username, data.id);
+                        c.disconnect(Some(&DisconnectPlayer {
+                            disconnect_reason: "You are already logged in".into(),
+                        }))
+                        .await;
+                        return;
+                    }
 
-                        buf.push(LevelFinalize::ID);
-                        buf.append(
-                            &mut classicl::to_bytes(&LevelFinalize {
-                                x_size: map.size.0,
-                                y_size: map.size.1,
-                                z_size: map.size.2,
-                            })
-                            .unwrap(),
+                    if data.data.unused == cpe::MAGIC {
+                        debug!("{} supports CPE", data.id);
+                        cpe::write_ext_info(&c).await;
+                        negotiations.insert(
+                            data.id,
+                            Negotiation {
+                                c,
+                                tx,
+                                username: data.data.username,
+                                addr,
+                                rank,
+                                frozen,
+                                count: None,
+                                entries: vec![],
+                            },
                         );
+                    } else {
+                        drop(negotiations);
+                        tx.send(()).unwrap();
+                        let mut player = Player::new(
+                            c,
+                            data.data.username,
+                            addr,
+                            Extensions::default(),
+                            rank,
+                            world.hacks_for(rank),
+                            spawn_point,
+                        );
+                        player.frozen = frozen;
+                        join(data.id, player, &mut players, &map, &opt, &world, &assets).await;
                     }
-                    c.write_bytes(buf).await;
+                }
+            });
+        }
+    });
 
-                    for (pid, p) in players.iter() {
-                        p.c.write_packet(&player.to_spawn(data.id)).await.unwrap();
-                        c.write_packet(&p.to_spawn(*pid)).await.unwrap();
-                    }
-                    c.write_packet(&player.to_spawn(-1)).await.unwrap();
-                    players.insert(data.id, player);
+    let handler = server.on_ext_info().unwrap();
+    let players = pdb.clone();
+    let negotiations = pn.clone();
+    let map = terrain.clone();
+    let opt = live.clone();
+    let world = settings.clone();
+    let assets = world_assets.clone();
+    tokio::spawn(async move {
+        let mut handler = handler.await.unwrap();
+        while let Some(data) = handler.get().await {
+            let players = players.clone();
+            let negotiations = negotiations.clone();
+            let map = map.clone();
+            let opt = opt.get();
+            let world = world.clone();
+            let assets = assets.clone();
+            tokio::spawn(async move {
+                let _timer = metrics::time_handler(Handler::ExtInfo);
+                if let Some(n) = negotiations.lock().await.get_mut(&data.id) {
+                    debug!(
+                        "{} uses {} with {} extensions",
+                        data.id,
+                        data.data.app_name.trim(),
+                        data.data.extension_count
+                    );
+                    n.count = Some(data.data.extension_count.max(0) as usize);
                 }
+                finish_negotiation(
+                    data.id,
+                    &negotiations,
+                    &players,
+                    &map,
+                    &opt,
+                    &world,
+                    &assets,
+                )
+                .await;
+            });
+        }
+    });
+
+    let handler = server.on_ext_entry().unwrap();
+    let players = pdb.clone();
+    let negotiations = pn.clone();
+    let map = terrain.clone();
+    let opt = live.clone();
+    let world = settings.clone();
+    let assets = world_assets.clone();
+    tokio::spawn(async move {
+        let mut handler = handler.await.unwrap();
+        while let Some(data) = handler.get().await {
+            let players = players.clone();
+            let negotiations = negotiations.clone();
+            let map = map.clone();
+            let opt = opt.get();
+            let world = world.clone();
+            let assets = assets.clone();
+            tokio::spawn(async move {
+                let _timer = metrics::time_handler(Handler::ExtEntry);
+                if let Some(n) = negotiations.lock().await.get_mut(&data.id) {
+                    n.entries.push(data.data);
+                }
+                finish_negotiation(
+                    data.id,
+                    &negotiations,
+                    &players,
+                    &map,
+                    &opt,
+                    &world,
+                    &assets,
+                )
+                .await;
             });
         }
     });
@@ -182,34 +491,86 @@ async fn main() {
     let players = pdb.clone();
     let map = terrain.clone();
     let changed = is_changed.clone();
+    let assets = world_assets.clone();
+    let changes = block_changes.clone();
+    let world = settings.clone();
+    let permissions = rank_permissions.clone();
     tokio::spawn(async move {
         let mut handler = handler.await.unwrap();
         while let Some(data) = handler.get().await {
             let players = players.clone();
             let changed = changed.clone();
             let map = map.clone();
+            let assets = assets.clone();
+            let changes = changes.clone();
+            let world = world.clone();
+            let permissions = permissions.clone();
             tokio::spawn(async move {
-                *changed.lock().await = true;
-                if players.lock().await.get(&data.id).is_some() {
-                    let block_type = if data.data.mode == 0x00 {
-                        terrain::blocks::AIR
-                    } else {
-                        data.data.block_type
-                    };
-                    map.lock()
-                        .await
-                        .set_block(data.data.x, data.data.y, data.data.z, block_type);
-                    for (_, player) in players.lock().await.iter_mut() {
+                let _timer = metrics::time_handler(Handler::SetBlock);
+                if let Some(player) = players.lock().await.get_mut(&data.id) {
+                    if player.selection.is_marking() {
+                        player.mark(&data.data, &map).await;
+                        return;
+                    }
+                }
+                let Some(rank) = players.lock().await.get(&data.id).map(|p| p.rank) else {
+                    return;
+                };
+                let block_type = if data.data.mode == 0x00 {
+                    terrain::blocks::AIR.into()
+                } else {
+                    data.data.block_type
+                };
+                let old = map
+                    .lock()
+                    .await
+                    .get_block(data.data.x, data.data.y, data.data.z);
+                if rank < world.lock().await.build_rank
+                    || !permissions.can_build(rank, block_type)
+                    || old.is_some_and(|b| !permissions.can_build(rank, b))
+                {
+                    if let Some(player) = players.lock().await.get(&data.id) {
+                        // The client already changed the block on its own
+                        if let Some(old) = old {
+                            player
+                                .write_block(data.data.x, data.data.y, data.data.z, old)
+                                .await;
+                        }
                         player
-                            .c
-                            .write_packet(&SetBlock {
-                                x: data.data.x,
-                                y: data.data.y,
-                                z: data.data.z,
-                                block_type,
-                            })
-                            .await
-                            .unwrap();
+                            .write_message("&cYou are not allowed to change this block".into())
+                            .await;
+                    }
+                    return;
+                }
+                *changed.lock().await = true;
+                metrics::BLOCK_CHANGES.add(1);
+                map.lock()
+                    .await
+                    .set_block(data.data.x, data.data.y, data.data.z, block_type);
+                changes
+                    .lock()
+                    .await
+                    .insert((data.data.x, data.data.y, data.data.z), block_type);
+                let effect = assets
+                    .block_break_effect
+                    .as_ref()
+                    .filter(|_| data.data.mode == 0x00)
+                    .and_then(|name| assets.effect_id(name))
+                    .map(|id| {
+                        // Particles fly away from the center of the broken block
+                        let center = |v: i16| i32::from(v) * 32 + 16;
+                        let pos = (
+                            center(data.data.x),
+                            center(data.data.y),
+                            center(data.data.z),
+                        );
+                        assets::spawn_effect(id, pos, pos)
+                    });
+                if let Some(effect) = &effect {
+                    for (_, player) in players.lock().await.iter() {
+                        if player.ext.has(cpe::CUSTOM_PARTICLES) {
+                            player.c.write_packet(effect).await.unwrap();
+                        }
                     }
                 }
             });
@@ -218,23 +579,42 @@ async fn main() {
 
     let handler = server.on_position_orientation().unwrap();
     let players = pdb.clone();
+    let map = terrain.clone();
     tokio::spawn(async move {
         let mut handler = handler.await.unwrap();
         while let Some(data) = handler.get().await {
             let players = players.clone();
+            let map = map.clone();
             tokio::spawn(async move {
+                let _timer = metrics::time_handler(Handler::PositionOrientation);
                 let mut players = players.lock().await;
                 let mut mplayer = None;
                 if let Some(player) = players.get_mut(&data.id) {
-                    player.set_pos_ori(&data.data);
-                    mplayer = Some(player.clone())
+                    let map = map.lock().await;
+                    if player.frozen {
+                        let p = &data.data;
+                        if (p.x, p.y, p.z) != (player.x, player.y, player.z) {
+                            player
+                                .teleport(player.x, player.y, player.z, player.yaw, player.pitch)
+                                .await;
+                        }
+                    } else if let Some(violation) = hacks::check(player, &data.data, &map) {
+                        warn!("{} moved using {violation} which is not allowed", data.id);
+                        player
+                            .teleport(player.x, player.y, player.z, player.yaw, player.pitch)
+                            .await;
+                    } else {
+                        player.set_pos_ori(&data.data);
+                        if hacks::is_grounded(&map, player.x, player.y, player.z) {
+                            player.ground_y = player.y;
+                        }
+                        mplayer = Some(player.clone())
+                    }
                 }
                 if let Some(player) = mplayer {
                     for (i, p) in players.iter_mut() {
                         if *i != data.id {
-                            p.c.write_packet(&player.to_pos_ori_upd(data.id))
-                                .await
-                                .unwrap();
+                            p.write_position(data.id, &player).await;
                         }
                     }
                 }
@@ -242,106 +622,144 @@ async fn main() {
         }
     });
 
-    let handler = server.on_message().unwrap();
+    let handler = server.on_player_click().unwrap();
     let players = pdb.clone();
+    let map = terrain.clone();
     tokio::spawn(async move {
         let mut handler = handler.await.unwrap();
         while let Some(data) = handler.get().await {
             let players = players.clone();
+            let map = map.clone();
             tokio::spawn(async move {
-                let mut players = players.lock().await;
-
+                let _timer = metrics::time_handler(Handler::PlayerClick);
+                let players = players.lock().await;
+                let click = &data.data;
                 if let Some(player) = players.get(&data.id) {
-                    let message: &str = data.data.message.trim();
-
-                    // Commands
-                    if let Some(cmd_text) = message.strip_prefix('/') {
-                        match Command::from_str(cmd_text) {
-                            Ok(cmd) => match cmd {
-                                Command::Tp(other_p) => {
-                                    if let Some((o_id, other_p)) = players
-                                        .iter()
-                                        .find(|(_, p)| p.player_name.trim() == other_p)
-                                    {
-                                        info!("{} teleported to {o_id}", data.id);
-                                        player
-                                            .c
-                                            .write_packet(&PositionOrientationTeleport {
-                                                player_id: -1,
-                                                x: other_p.x,
-                                                y: other_p.y,
-                                                z: other_p.z,
-                                                yaw: other_p.yaw,
-                                                pitch: 0,
-                                            })
-                                            .await
-                                            .unwrap();
-                                    } else {
-                                        debug!("{} tried to teleport to {other_p}", data.id);
-                                        player
-                                            .write_message(format!(
-                                                "&cCould not find player `{}`",
-                                                other_p
-                                            ))
-                                            .await;
-                                    }
-                                }
-                            },
-                            Err(e) => {
-                                debug!("{} tried to execute `{message}`", data.id);
-                                match e {
-                                    commands::CommandError::NoCommand => {
-                                        player
-                                            .write_message(format!(
-                                                "&c`{}` is not a command",
-                                                message
-                                            ))
-                                            .await
-                                    }
-                                    commands::CommandError::CommandNotKnown => {
-                                        player
-                                            .write_message(format!("&c`{}` is not known", message))
-                                            .await
-                                    }
-                                    commands::CommandError::TooManyArguments => {
-                                        player
-                                            .write_message(format!(
-                                                "&c`{}` has too many arguments",
-                                                message
-                                            ))
-                                            .await
-                                    }
-                                    commands::CommandError::NotEnoughArguments => {
-                                        player
-                                            .write_message(format!(
-                                                "&c`{}` has not enough arguments",
-                                                message
-                                            ))
-                                            .await
-                                    }
-                                }
-                            }
+                    if !player.block_info || click.action != cpe::CLICK_PRESSED {
+                        return;
+                    }
+                    if let Some(target) = players.get(&click.target_entity_id) {
+                        let mut message = format!("&eThis is {}", target.player_name.trim());
+                        if target.ext.has(cpe::HELD_BLOCK) {
+                            message.push_str(&format!(" holding block {}", target.held_block));
                         }
-                    } else {
-                        let mut message = format!(
-                            "&7{}:&f {}",
-                            player.player_name.trim(),
-                            data.data.message.trim()
-                        );
-                        info!(
-                            "{} wrote: {}",
-                            player.player_name.trim(),
-                            data.data.message.trim()
-                        );
-                        message.truncate(64);
-                        for (_, p) in players.iter_mut() {
-                            p.c.write_packet(&Message {
-                                player_id: data.id,
-                                message: message.clone(),
+                        player.write_message(message).await;
+                    } else if let Some(block) = map.lock().await.get_block(
+                        click.target_block_x,
+                        click.target_block_y,
+                        click.target_block_z,
+                    ) {
+                        player
+                            .write_message(format!(
+                                "&eBlock {block} at ({}, {}, {})",
+                                click.target_block_x, click.target_block_y, click.target_block_z
+                            ))
+                            .await;
+                    }
+                }
+            });
+        }
+    });
+
+    let handler = server.on_two_way_ping().unwrap();
+    let players = pdb.clone();
+    tokio::spawn(async move {
+        let mut handler = handler.await.unwrap();
+        while let Some(data) = handler.get().await {
+            let players = players.clone();
+            tokio::spawn(async move {
+                let _timer = metrics::time_handler(Handler::TwoWayPing);
+                if let Some(player) = players.lock().await.get_mut(&data.id) {
+                    if data.data.direction == latency::FROM_CLIENT {
+                        player
+                            .c
+                            .write_packet(&TwoWayPing {
+                                direction: latency::FROM_CLIENT,
+                                data: data.data.data,
                             })
                             .await
                             .unwrap();
-                        }
+                    } else if let Some(rtt) = player.latency.finish(data.data.data) {
+                        debug!("{} has a latency of {} ms", data.id, rtt.as_millis());
+                    }
+                }
+            });
+        }
+    });
+
+    let players = pdb.clone();
+    tokio::spawn(async move {
+        loop {
+            time::sleep(latency::INTERVAL).await;
+            let mut players = players.lock().await;
+            let mut changed = vec![];
+            for (id, p) in players.iter_mut() {
+                if p.ext.has(cpe::TWO_WAY_PING) {
+                    let ping = p.latency.start();
+                    p.c.write_packet(&ping).await.unwrap();
+                }
+                if p.latency.update_shown() {
+                    changed.push(*id);
+                }
+            }
+            // Refresh the latencies shown in the tab list
+            for (_, p) in players.iter() {
+                for id in &changed {
+                    p.write_list_entry(*id, &players[id]).await;
+                }
+            }
+        }
+    });
+
+    let handler = server.on_message().unwrap();
+    let shared = state.clone();
+    tokio::spawn(async move {
+        let mut handler = handler.await.unwrap();
+        while let Some(data) = handler.get().await {
+            let state = shared.clone();
+            tokio::spawn(async move {
+                let _timer = metrics::time_handler(Handler::Message);
+                let message = data.data.message.trim();
+
+                // Commands
+                if let Some(cmd_text) = message.strip_prefix('/') {
+                    let sender = state
+                        .players
+                        .lock()
+                        .await
+                        .get(&data.id)
+                        .map(|p| Sender::player(data.id, p));
+                    if let Some(mut sender) = sender {
+                        execute(&mut sender, cmd_text, &state).await;
+                    }
+                    return;
+                }
+
+                let players = state.players.lock().await;
+                let Some(player) = players.get(&data.id) else {
+                    return;
+                };
+                if let Some(muted) = mute_message(&player.player_name, &state.mutes).await {
+                    player.write_message(muted).await;
+                } else {
+                    let mut message = format!(
+                        "{}{}:&f {message}",
+                        player.rank.color(),
+                        player.player_name.trim(),
+                    );
+                    info!(
+                        "{} wrote: {}",
+                        player.player_name.trim(),
+                        data.data.message.trim()
+                    );
+                    message.truncate(64);
+                    for (_, p) in players.iter() {
+                        p.c.write_packet(&Message {
+                            player_id: data.id,
+                            message: message.clone(),
+                        })
+                        .await
+                        .unwrap();
                     }
                 }
             });
@@ -351,89 +769,282 @@ async fn main() {
     let handler = server.on_client_disconnected().unwrap();
     let players = pdb.clone();
     let queue = pq.clone();
+    let negotiations = pn.clone();
+    let seen = last_seen.clone();
+    let opt = live.clone();
     tokio::spawn(async move {
         let mut handler = handler.await.unwrap();
         while let Some(data) = handler.get().await {
-            let _ = players.lock().await.remove(&data.id);
+            let mut players = players.lock().await;
             let _ = queue.lock().await.remove(&data.id);
-            for (_, p) in players.lock().await.iter_mut() {
-                p.c.write_packet(&DespawnPlayer { player_id: data.id })
-                    .await
-                    .unwrap();
+            let _ = negotiations.lock().await.remove(&data.id);
+            // Already gone if another session of the same name replaced it
+            if let Some(p) = players.remove(&data.id) {
+                leave(data.id, &p, &players, &seen, &opt.get()).await;
             }
         }
     });
 
     let map = terrain.clone();
-    let opt = cli.clone();
+    let opt = live.clone();
     let changed = is_changed.clone();
+    let seen = last_seen.clone();
     tokio::spawn(async move {
         loop {
-            time::sleep(Duration::from_secs(120)).await;
+            let interval = opt.get().autosave;
+            if interval == 0 {
+                // Look again later in case `/reload` enabled saving
+                time::sleep(Duration::from_secs(10)).await;
+                continue;
+            }
+            time::sleep(Duration::from_secs(interval)).await;
             let mut changed = changed.lock().await;
             if *changed {
                 debug!("Trying to save the map.");
                 *changed = false;
-                save_map(opt.clone(), map.clone()).await;
+                save_map(opt.get(), map.clone()).await;
             } else {
                 debug!("Map not changed. Save discarded")
             }
+            // Saved here instead of on every disconnect, several players often leave at once
+            seen.lock().await.save();
         }
     });
 
-    let map = terrain.clone();
-    let opt = cli.clone();
-    let ctrl_c = tokio::spawn(async move {
-        tokio::signal::ctrl_c().await.unwrap();
-        info!("Saving map and stopping server now.");
-        save_map(opt, map).await;
-    });
-
-    let opt = cli.clone();
-    tokio::select! {
-        _ = server.run(opt.limit) => (),
-        _ = ctrl_c => (),
+    select! {
+        _ = server.run(None) => (),
+        _ = tokio::signal::ctrl_c() => (),
+        _ = state.stop.notified() => (),
     }
+    info!("Saving map and stopping server now.");
+    save_map(live.get(), terrain).await;
+    last_seen.lock().await.save();
+}
+
+/// Shared state of the server, needed to run commands from anywhere
+#[derive(Clone)]
+struct State {
+    cli: config::Live,
+    loader: Arc<config::Loader>,
+    players: Arc<Mutex<HashMap<i8, Player>>>,
+    map: Arc<Mutex<Terrain>>,
+    world: Arc<Mutex<WorldSettings>>,
+    assets: Arc<Assets>,
+    changed: Arc<Mutex<bool>>,
+    changes: Arc<Mutex<BlockChanges>>,
+    ranks: Arc<Mutex<Ranks>>,
+    permissions: Arc<Permissions>,
+    bans: Arc<std::sync::Mutex<Bans>>,
+    whitelist: Arc<Mutex<Whitelist>>,
+    seen: Arc<Mutex<LastSeen>>,
+    /// Ends of mutes by lowercase name, kept when muted players reconnect
+    mutes: Arc<Mutex<HashMap<String, Instant>>>,
+    /// Lowercase names of frozen players, kept when they reconnect
+    frozen: Arc<Mutex<HashSet<String>>>,
+    locations: Arc<Mutex<Locations>>,
+    /// Notified by `/stop`
+    stop: Arc<Notify>,
+    started: Instant,
 }
 
 #[derive(Clone)]
 pub struct Player {
     pub c: ClientController,
     pub player_name: String,
-    pub x: i16,
-    pub y: i16,
-    pub z: i16,
+    pub addr: IpAddr,
+    pub ext: Extensions,
+    pub rank: Rank,
+    pub hacks: Hacks,
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
     pub yaw: u8,
     pub pitch: u8,
+    /// Height of the last position the player was standing on
+    pub ground_y: i32,
+    pub selection: Selection,
+    pub held_block: u16,
+    /// Whether clicking reports information about the clicked block or player
+    pub block_info: bool,
+    pub appearance: Appearance,
+    pub latency: Latency,
+    /// Name of whoever sent the last private message, answered by `/r`
+    pub reply_to: Option<String>,
+    /// Frozen players are teleported back whenever they move, see [`State::frozen`].
+    pub frozen: bool,
+    /// Position the client respawns at instead of the spawn point of the world, set by
+    /// `/checkpoint`
+    pub spawnpoint: Option<(i32, i32, i32)>,
+    /// When VelocityControl last changed the velocity of the player
+    pub launched: Option<Instant>,
 }
 
 impl Player {
-    pub fn to_spawn(&self, id: i8) -> SpawnPlayer {
-        SpawnPlayer {
-            player_id: id,
-            player_name: self.player_name.to_string(),
-            x: self.x,
-            y: self.y,
-            z: self.z,
-            yaw: self.yaw,
-            pitch: self.pitch,
+    pub fn new(
+        c: ClientController,
+        player_name: String,
+        addr: IpAddr,
+        ext: Extensions,
+        rank: Rank,
+        hacks: Hacks,
+        spawn_point: (i32, i32, i32),
+    ) -> Self {
+        Self {
+            c,
+            player_name,
+            addr,
+            ext,
+            rank,
+            hacks,
+            x: spawn_point.0,
+            y: spawn_point.1,
+            z: spawn_point.2,
+            yaw: 0,
+            pitch: 0,
+            ground_y: spawn_point.1,
+            selection: Selection::None,
+            held_block: terrain::blocks::AIR.into(),
+            block_info: false,
+            appearance: Appearance::default(),
+            latency: Latency::default(),
+            reply_to: None,
+            frozen: false,
+            spawnpoint: None,
+            launched: None,
+        }
+    }
+
+    /// Spawns `p` with the given id and appearance for this player.
+    pub async fn write_spawn(&self, id: i8, p: &Player) {
+        let mut buf = vec![];
+        if self.ext.has(cpe::EXT_ENTITY_POSITIONS) {
+            push_packet(
+                &mut buf,
+                &ExtSpawnPlayer {
+                    player_id: id,
+                    player_name: p.player_name.to_string(),
+                    x: p.x,
+                    y: p.y,
+                    z: p.z,
+                    yaw: p.yaw,
+                    pitch: p.pitch,
+                },
+            );
+        } else {
+            push_packet(
+                &mut buf,
+                &SpawnPlayer {
+                    player_id: id,
+                    player_name: p.player_name.to_string(),
+                    x: to_short(p.x),
+                    y: to_short(p.y),
+                    z: to_short(p.z),
+                    yaw: p.yaw,
+                    pitch: p.pitch,
+                },
+            );
+        }
+        self.push_appearance(&mut buf, id, p, true);
+        let _ = self.c.write_bytes(buf).await;
+    }
+
+    /// Updates the model and entity properties of `p` with the given id for this player.
+    pub async fn write_appearance(&self, id: i8, p: &Player) {
+        let mut buf = vec![];
+        self.push_appearance(&mut buf, id, p, false);
+        if !buf.is_empty() {
+            let _ = self.c.write_bytes(buf).await;
         }
     }
 
-    // ClassiCube does not understand PositionOrientationUpdate
-    // maybe this is not meant to be used
-    pub fn to_pos_ori_upd(&self, id: i8) -> client::PositionOrientation {
-        client::PositionOrientation {
-            player_id: id.to_be_bytes()[0],
-            x: self.x,
-            y: self.y,
-            z: self.z,
-            yaw: self.yaw,
-            pitch: self.pitch,
+    /// Newly spawned entities already have the default model, otherwise it is always sent so
+    /// resetting the model is shown.
+    fn push_appearance(&self, buf: &mut Vec<u8>, id: i8, p: &Player, spawn: bool) {
+        if self.ext.has(cpe::CHANGE_MODEL) && (p.appearance.model.is_some() || !spawn) {
+            push_packet(buf, &p.appearance.to_change_model(id));
+        }
+        if self.ext.has(cpe::ENTITY_PROPERTY) {
+            for property in p.appearance.to_entity_properties(id) {
+                push_packet(buf, &property);
+            }
         }
     }
 
-    pub fn set_pos_ori(&mut self, p: &client::PositionOrientation) {
+    /// Moves the player with the given id to the position of `p` for this player.
+    ///
+    /// ClassiCube does not understand PositionOrientationUpdate so the absolute
+    /// position is always sent.
+    pub async fn write_position(&self, id: i8, p: &Player) {
+        self.write_teleport(id, p.x, p.y, p.z, p.yaw, p.pitch).await;
+    }
+
+    async fn write_teleport(&self, id: i8, x: i32, y: i32, z: i32, yaw: u8, pitch: u8) {
+        if self.ext.has(cpe::EXT_ENTITY_POSITIONS) {
+            self.c
+                .write_packet(&ExtPositionOrientationTeleport {
+                    player_id: id,
+                    x,
+                    y,
+                    z,
+                    yaw,
+                    pitch,
+                })
+                .await
+                .unwrap();
+        } else {
+            self.c
+                .write_packet(&PositionOrientationTeleport {
+                    player_id: id,
+                    x: to_short(x),
+                    y: to_short(y),
+                    z: to_short(z),
+                    yaw,
+                    pitch,
+                })
+                .await
+                .unwrap();
+        }
+    }
+
+    fn push_level(&self, buf: &mut Vec<u8>, map: &Terrain) {
+        let fast_map = self.ext.has(cpe::FAST_MAP);
+        if fast_map {
+            push_packet(
+                buf,
+                &ExtLevelInitialize {
+                    map_size: map.volume() as i32,
+                },
+            );
+        } else {
+            push_packet(buf, &LevelInitialize {});
+        }
+        for i in map.to_chunks(fast_map, self.ext.has(cpe::EXTENDED_BLOCKS)) {
+            push_packet(buf, &i);
+        }
+
+        push_packet(
+            buf,
+            &LevelFinalize {
+                x_size: map.size.0,
+                y_size: map.size.1,
+                z_size: map.size.2,
+            },
+        );
+    }
+
+    /// Sends the whole level again and puts the player back where they were.
+    pub async fn resend_level(&self, map: &Terrain) {
+        let mut buf = vec![];
+        self.push_level(&mut buf, map);
+        let _ = self.c.write_bytes(buf).await;
+        self.write_position(-1, self).await;
+    }
+
+    pub fn set_pos_ori(&mut self, p: &client::ExtPositionOrientation) {
+        if self.ext.has(cpe::HELD_BLOCK) {
+            // The player id is replaced by the held block
+            self.held_block = p.player_id;
+        }
         self.x = p.x;
         self.y = p.y;
         self.z = p.z;
@@ -441,6 +1052,245 @@ impl Player {
         self.pitch = p.pitch;
     }
 
+    /// Moves the player to the given position, which is also their last valid position afterwards.
+    pub async fn teleport(&mut self, x: i32, y: i32, z: i32, yaw: u8, pitch: u8) {
+        self.x = x;
+        self.y = y;
+        self.z = z;
+        self.yaw = yaw;
+        self.pitch = pitch;
+        self.ground_y = y;
+        self.write_teleport(-1, x, y, z, yaw, pitch).await;
+    }
+
+    /// Uses a block change of the player to mark a corner of their selection.
+    pub async fn mark(&mut self, b: &client::ExtSetBlock, map: &Mutex<Terrain>) {
+        // The client already changed the block on its own
+        if let Some(block_type) = map.lock().await.get_block(b.x, b.y, b.z) {
+            self.write_block(b.x, b.y, b.z, block_type).await;
+        }
+        if let Some(cuboid) = self.selection.mark((b.x, b.y, b.z)) {
+            let (x, y, z) = cuboid.size();
+            self.write_message(format!(
+                "&eSelected {x}x{y}x{z} ({} blocks)",
+                cuboid.volume()
+            ))
+            .await;
+            if self.ext.has(cpe::SELECTION_CUBOID) {
+                self.c
+                    .write_packet(&cuboid.to_make_selection())
+                    .await
+                    .unwrap();
+            }
+        } else {
+            self.write_message(format!("&eMarked ({}, {}, {})", b.x, b.y, b.z))
+                .await;
+        }
+    }
+
+    /// Sends a block change, replacing blocks the client does not know.
+    pub async fn write_block(&self, x: i16, y: i16, z: i16, block_type: u16) {
+        if self.ext.has(cpe::EXTENDED_BLOCKS) {
+            self.c
+                .write_packet(&ExtSetBlock {
+                    x,
+                    y,
+                    z,
+                    block_type,
+                })
+                .await
+                .unwrap();
+        } else {
+            self.c
+                .write_packet(&SetBlock {
+                    x,
+                    y,
+                    z,
+                    block_type: block_type.try_into().unwrap_or(terrain::blocks::FALLBACK),
+                })
+                .await
+                .unwrap();
+        }
+    }
+
+    pub async fn hold(&self, block: u16, prevent_change: bool) {
+        if self.ext.has(cpe::EXTENDED_BLOCKS) {
+            self.c
+                .write_packet(&ExtHoldThis {
+                    block_to_hold: block,
+                    prevent_change: prevent_change.into(),
+                })
+                .await
+                .unwrap();
+        } else {
+            self.c
+                .write_packet(&HoldThis {
+                    block_to_hold: block.try_into().unwrap_or(terrain::blocks::FALLBACK),
+                    prevent_change: prevent_change.into(),
+                })
+                .await
+                .unwrap();
+        }
+    }
+
+    /// Re-sends the server name, MOTD and allowed hacks to a client supporting InstantMOTD.
+    pub async fn write_identification(&self, cli: &Cli, world: &WorldSettings) {
+        let mut buf = vec![];
+        self.push_identification(&mut buf, cli, world);
+        let _ = self.c.write_bytes(buf).await;
+    }
+
+    fn push_identification(&self, buf: &mut Vec<u8>, cli: &Cli, world: &WorldSettings) {
+        let name = world.name.as_ref().unwrap_or(&cli.name);
+        let motd = world.motd.as_ref().unwrap_or(&cli.motd);
+        let flags = self.hacks.motd_flags();
+        let motd = if flags.is_empty() {
+            motd.clone()
+        } else {
+            format!("{motd} {flags}")
+        };
+        push_packet(
+            buf,
+            &ServerIdentification {
+                protocol_version: 0x07,
+                server_name: name.clone(),
+                server_motd: motd,
+                user_type: self.rank.user_type(),
+            },
+        );
+        if self.ext.has(cpe::HACK_CONTROL) {
+            push_packet(buf, &self.hacks.to_hack_control());
+        }
+    }
+
+    /// Changes the rank and the hacks that come with it.
+    pub async fn set_rank(&mut self, rank: Rank, hacks: Hacks) {
+        self.rank = rank;
+        self.hacks = hacks;
+        let mut buf = vec![];
+        push_packet(
+            &mut buf,
+            &UpdateUserType {
+                user_type: rank.user_type(),
+            },
+        );
+        if self.ext.has(cpe::HACK_CONTROL) {
+            push_packet(&mut buf, &self.hacks.to_hack_control());
+        }
+        let _ = self.c.write_bytes(buf).await;
+    }
+
+    /// Hides the HUD and shows black bars like in a cutscene.
+    pub async fn set_cinematic(&self, on: bool) {
+        self.c
+            .write_packet(&CinematicGui {
+                hide_crosshair: on.into(),
+                hide_hand: on.into(),
+                hide_hotbar: on.into(),
+                opacity: 0xff,
+                bar_size: if on { CINEMATIC_BAR_SIZE } else { 0 },
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+    }
+
+    /// Sets the position the player returns to when respawning.
+    pub async fn set_spawnpoint(&mut self, x: i32, y: i32, z: i32, yaw: u8, pitch: u8) {
+        self.spawnpoint = Some((x, y, z));
+        if self.ext.has(cpe::EXT_ENTITY_POSITIONS) {
+            self.c
+                .write_packet(&ExtSetSpawnpoint {
+                    x,
+                    y,
+                    z,
+                    yaw,
+                    pitch,
+                })
+                .await
+                .unwrap();
+        } else {
+            self.c
+                .write_packet(&SetSpawnpoint {
+                    x: to_short(x),
+                    y: to_short(y),
+                    z: to_short(z),
+                    yaw,
+                    pitch,
+                })
+                .await
+                .unwrap();
+        }
+    }
+
+    /// Changes the velocity of the player in blocks per tick, either replacing or adding to the
+    /// current one.
+    pub async fn set_velocity(&mut self, (x, y, z): (f64, f64, f64), replace: bool) {
+        self.launched = Some(Instant::now());
+        let to_fixed = |v: f64| (v * VELOCITY_SCALE).round() as i32;
+        let mode = replace.into();
+        self.c
+            .write_packet(&VelocityControl {
+                x: to_fixed(x),
+                y: to_fixed(y),
+                z: to_fixed(z),
+                x_mode: mode,
+                y_mode: mode,
+                z_mode: mode,
+            })
+            .await
+            .unwrap();
+    }
+
+    /// Puts a block into the hotbar slot with the given index, starting at 0.
+    pub async fn set_hotbar(&self, index: u8, block: u16) {
+        if self.ext.has(cpe::EXTENDED_BLOCKS) {
+            self.c
+                .write_packet(&ExtSetHotbar {
+                    block_id: block,
+                    hotbar_index: index,
+                })
+                .await
+                .unwrap();
+        } else {
+            self.c
+                .write_packet(&SetHotbar {
+                    block_id: block.try_into().unwrap_or(terrain::blocks::FALLBACK),
+                    hotbar_index: index,
+                })
+                .await
+                .unwrap();
+        }
+    }
+
+    /// Adds or updates the tab list entry of `p` with the given id for this player.
+    pub async fn write_list_entry(&self, id: i8, p: &Player) {
+        if !self.ext.has(cpe::EXT_PLAYER_LIST) {
+            return;
+        }
+        let name = p.player_name.trim();
+        let color = p.rank.color();
+        let list_name = match p.latency.rtt() {
+            Some(rtt) => format!("{color}{name} &7{} ms", rtt.as_millis()),
+            None => format!("{color}{name}"),
+        };
+        self.c
+            .write_packet(&ExtAddPlayerName {
+                name_id: id.into(),
+                player_name: name.to_string(),
+                list_name,
+                group_name: p.rank.to_string(),
+                group_rank: p.rank as u8,
+            })
+            .await
+            .unwrap();
+    }
+
+    /// Sends several messages which arrive in order.
+    pub async fn write_lines(&self, lines: Vec<String>) {
+        write_lines(&self.c, lines).await;
+    }
+
     pub async fn write_message(&self, mut message: String) {
         message.truncate(64);
         self.c
@@ -453,17 +1303,1237 @@ impl Player {
     }
 }
 
-fn to_fixed_point(v: f64) -> i16 {
-    (v * 32.0).round() as i16
+/// Whoever runs a command, either a player or the console
+pub struct Sender {
+    /// `None` unless the sender is a player
+    pub id: Option<i8>,
+    pub name: String,
+    pub rank: Rank,
+    output: Output,
+}
+
+enum Output {
+    Player(ClientController),
+    /// Replies collected to be shown somewhere else
+    Lines(Vec<String>),
+}
+
+impl Sender {
+    pub fn player(id: i8, player: &Player) -> Self {
+        Self {
+            id: Some(id),
+            name: player.player_name.trim().to_string(),
+            rank: player.rank,
+            output: Output::Player(player.c.clone()),
+        }
+    }
+
+    /// A sender with every permission whose replies are collected, see [`Sender::take_lines`]
+    pub fn console(name: &str) -> Self {
+        Self {
+            id: None,
+            name: name.to_string(),
+            rank: Rank::Owner,
+            output: Output::Lines(vec![]),
+        }
+    }
+
+    pub async fn reply(&mut self, message: String) {
+        self.reply_lines(vec![message]).await;
+    }
+
+    pub async fn reply_lines(&mut self, lines: Vec<String>) {
+        match &mut self.output {
+            Output::Player(c) => write_lines(c, lines).await,
+            Output::Lines(output) => output.extend(lines),
+        }
+    }
+
+    /// Replies collected since the last call, always empty for players
+    pub fn take_lines(&mut self) -> Vec<String> {
+        match &mut self.output {
+            Output::Player(_) => vec![],
+            Output::Lines(output) => std::mem::take(output),
+        }
+    }
+
+    /// Name of the player a command acts on, the sender itself unless another player is named
+    async fn target(&mut self, target: Option<String>) -> Option<String> {
+        match target {
+            Some(name) => Some(name),
+            None if self.id.is_some() => Some(self.name.clone()),
+            None => {
+                self.reply("&cName a player when not in the game".into())
+                    .await;
+                None
+            }
+        }
+    }
+}
+
+/// Parses and runs a command given without the leading `/`.
+async fn execute(sender: &mut Sender, text: &str, state: &State) {
+    let extended_blocks = match sender.id {
+        Some(id) => {
+            let players = state.players.lock().await;
+            players
+                .get(&id)
+                .is_some_and(|p| p.ext.has(cpe::EXTENDED_BLOCKS))
+        }
+        None => true,
+    };
+    let max_block = if extended_blocks {
+        blocks::MAX_EXTENDED_ID
+    } else {
+        blocks::MAX_ID
+    };
+    let (info, cmd) = match commands::parse(text, max_block) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            debug!("{} tried to execute `{text}`", sender.name);
+            sender
+                .reply_lines(commands::wrap("&c", &e.to_string()))
+                .await;
+            return;
+        }
+    };
+    if !state.permissions.can_use(sender.rank, info.name) {
+        info!("{} is not allowed to use /{}", sender.name, info.name);
+        sender
+            .reply(format!("&cYou are not allowed to use /{}", info.name))
+            .await;
+        return;
+    }
+    if !info.runner.allows(sender.id.is_some()) {
+        let place = if sender.id.is_some() {
+            "from the console"
+        } else {
+            "in the game"
+        };
+        sender
+            .reply(format!("&c/{} can only be used {place}", info.name))
+            .await;
+        return;
+    }
+    if sender.id.is_some() && matches!(cmd, Command::Msg(..) | Command::Reply(_) | Command::Me(_)) {
+        if let Some(muted) = mute_message(&sender.name, &state.mutes).await {
+            sender.reply(muted).await;
+            return;
+        }
+    }
+    run_command(sender, cmd, state).await;
+}
+
+/// Runs a command which the sender is allowed to run, see [`execute`].
+async fn run_command(sender: &mut Sender, cmd: Command, state: &State) {
+    let mut players = state.players.lock().await;
+    // Commands which need a player are never run by the console
+    let me = sender.id.and_then(|id| players.get(&id));
+    match cmd {
+        Command::Select => {
+            if let Some(player) = sender.id.and_then(|id| players.get_mut(&id)) {
+                player.selection = Selection::Marking;
+            }
+            sender
+                .reply("&ePlace or break two blocks to mark the corners".into())
+                .await;
+        }
+        Command::Deselect => {
+            let Some(player) = sender.id.and_then(|id| players.get_mut(&id)) else {
+                return;
+            };
+            player.selection = Selection::None;
+            if player.ext.has(cpe::SELECTION_CUBOID) {
+                player
+                    .c
+                    .write_packet(&selection::remove_selection())
+                    .await
+                    .unwrap();
+            }
+            sender.reply("&eSelection cleared".into()).await;
+        }
+        Command::BlockInfo => {
+            let Some(player) = sender.id.and_then(|id| players.get_mut(&id)) else {
+                return;
+            };
+            player.block_info = !player.block_info;
+            let message = if !player.ext.has(cpe::PLAYER_CLICK) {
+                "&cYour client does not support clicking"
+            } else if player.block_info {
+                "&eClick a block or player to get information"
+            } else {
+                "&eBlock info disabled"
+            };
+            sender.reply(message.into()).await;
+        }
+        Command::Ping(target) => {
+            let Some(name) = sender.target(target).await else {
+                return;
+            };
+            let message = match players.values().find(|p| p.player_name.trim() == name) {
+                None => "&cCould not find that player".to_string(),
+                Some(t) if !t.ext.has(cpe::TWO_WAY_PING) => {
+                    format!("&c{name} does not support measuring latency")
+                }
+                Some(t) => match t.latency.rtt() {
+                    Some(rtt) => format!("&e{name} has a ping of {} ms", rtt.as_millis()),
+                    None => format!("&eThe ping of {name} is not known yet"),
+                },
+            };
+            sender.reply(message).await;
+        }
+        Command::Hotbar(slot, block) => {
+            let Some(player) = me else {
+                return;
+            };
+            if player.ext.has(cpe::SET_HOTBAR) {
+                player.set_hotbar(slot - 1, block).await;
+            } else {
+                sender
+                    .reply("&cYour client does not support setting the hotbar".into())
+                    .await;
+            }
+        }
+        Command::Launch(velocity, target) => {
+            let Some(name) = sender.target(target).await else {
+                return;
+            };
+            match players.values_mut().find(|p| p.player_name.trim() == name) {
+                Some(t) if t.ext.has(cpe::VELOCITY_CONTROL) => {
+                    info!("{} launched {name}", sender.name);
+                    t.set_velocity(velocity, true).await;
+                }
+                Some(_) => sender.reply(format!("&c{name} can not be launched")).await,
+                None => sender.reply("&cCould not find that player".into()).await,
+            }
+        }
+        Command::Checkpoint => {
+            let Some(player) = sender.id.and_then(|id| players.get_mut(&id)) else {
+                return;
+            };
+            if player.ext.has(cpe::SET_SPAWNPOINT) {
+                player
+                    .set_spawnpoint(player.x, player.y, player.z, player.yaw, player.pitch)
+                    .await;
+                sender.reply("&eCheckpoint set".into()).await;
+            } else {
+                sender
+                    .reply("&cYour client does not support checkpoints".into())
+                    .await;
+            }
+        }
+        Command::Promote(name) => {
+            let world = state.world.lock().await.clone();
+            change_rank(
+                sender,
+                &name,
+                Rank::next,
+                &mut players,
+                &state.ranks,
+                &world,
+            )
+            .await;
+        }
+        Command::Demote(name) => {
+            let world = state.world.lock().await.clone();
+            change_rank(
+                sender,
+                &name,
+                Rank::previous,
+                &mut players,
+                &state.ranks,
+                &world,
+            )
+            .await;
+        }
+        Command::Ban(name, reason) => {
+            ban_player(
+                sender,
+                &name,
+                None,
+                reason,
+                &players,
+                &state.ranks,
+                &state.bans,
+            )
+            .await;
+        }
+        Command::TempBan(name, duration, reason) => {
+            ban_player(
+                sender,
+                &name,
+                Some(duration),
+                reason,
+                &players,
+                &state.ranks,
+                &state.bans,
+            )
+            .await;
+        }
+        Command::BanIp(target, reason) => {
+            let net = bans::parse_net(&target).or_else(|| {
+                players
+                    .values()
+                    .find(|p| p.player_name.trim() == target)
+                    .map(|p| p.addr.into())
+            });
+            let Some(net) = net else {
+                sender
+                    .reply(format!("&c`{target}` is neither an address nor online"))
+                    .await;
+                return;
+            };
+            let protected = players
+                .values()
+                .find(|p| net.contains(&p.addr) && !can_punish(sender.rank, p.rank));
+            if let Some(p) = protected {
+                sender
+                    .reply(format!(
+                        "&cYou can not ban the address of {}",
+                        p.player_name.trim()
+                    ))
+                    .await;
+                return;
+            }
+            info!("{} banned {net}", sender.name);
+            let ban = Ban::new(&sender.name, reason, None);
+            let reason = DisconnectPlayer {
+                disconnect_reason: ban.message(),
+            };
+            state.bans.lock().unwrap().ban_ip(net, ban);
+            for p in players.values().filter(|p| net.contains(&p.addr)) {
+                p.c.disconnect(Some(&reason)).await;
+            }
+            sender.reply(format!("&e{net} is banned")).await;
+        }
+        Command::Unban(target) => {
+            let unbanned = state.bans.lock().unwrap().unban(&target);
+            if unbanned {
+                info!("{} unbanned {target}", sender.name);
+                sender.reply(format!("&e{target} is unbanned")).await;
+            } else {
+                sender.reply(format!("&c{target} is not banned")).await;
+            }
+        }
+        Command::Whitelist(action) => {
+            let mut whitelist = state.whitelist.lock().await;
+            let message = match action {
+                WhitelistAction::Add(name) => {
+                    if whitelist.add(&name) {
+                        info!("{} whitelisted {name}", sender.name);
+                        format!("&e{name} is whitelisted")
+                    } else {
+                        format!("&c{name} is already whitelisted")
+                    }
+                }
+                WhitelistAction::Remove(name) => {
+                    if whitelist.remove(&name) {
+                        info!("{} unwhitelisted {name}", sender.name);
+                        format!("&e{name} is no longer whitelisted")
+                    } else {
+                        format!("&c{name} is not whitelisted")
+                    }
+                }
+                WhitelistAction::List => {
+                    let names = whitelist
+                        .players()
+                        .map(|n| n.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    sender
+                        .reply_lines(commands::wrap("&e", &format!("Whitelisted: {names}")))
+                        .await;
+                    return;
+                }
+                WhitelistAction::On => {
+                    info!("{} enabled the whitelist", sender.name);
+                    whitelist.set_enabled(true);
+                    "&eOnly whitelisted players can join now".into()
+                }
+                WhitelistAction::Off => {
+                    info!("{} disabled the whitelist", sender.name);
+                    whitelist.set_enabled(false);
+                    "&eEveryone can join now".into()
+                }
+            };
+            sender.reply(message).await;
+        }
+        Command::Help(None) => {
+            let names = commands::COMMANDS
+                .iter()
+                .filter(|c| {
+                    state.permissions.can_use(sender.rank, c.name)
+                        && c.runner.allows(sender.id.is_some())
+                })
+                .map(|c| c.name)
+                .collect::<Vec<_>>()
+                .join(", ");
+            let mut lines = commands::wrap("&e", &format!("Commands: {names}"));
+            lines.push("&eSee /help <command> for details".into());
+            sender.reply_lines(lines).await;
+        }
+        Command::Help(Some(name)) => {
+            let Some(info) = commands::find(&name) else {
+                let e = commands::CommandError::CommandNotKnown(name);
+                sender.reply(format!("&c{e}")).await;
+                return;
+            };
+            let mut lines = commands::wrap("&e", &info.usage());
+            lines.extend(commands::wrap("&7", info.help));
+            if !info.aliases.is_empty() {
+                lines.extend(commands::wrap(
+                    "&7",
+                    &format!("Aliases: {}", info.aliases.join(", ")),
+                ));
+            }
+            sender.reply_lines(lines).await;
+        }
+        Command::Msg(name, message) => {
+            whisper(sender, &name, &message, &mut players).await;
+        }
+        Command::Reply(message) => match me.and_then(|p| p.reply_to.clone()) {
+            Some(name) => {
+                whisper(sender, &name, &message, &mut players).await;
+            }
+            None => {
+                sender.reply("&cNobody has messaged you yet".into()).await;
+            }
+        },
+        Command::Me(action) => {
+            let message = format!("* {} {action}", sender.name);
+            info!("{message}");
+            for p in players.values() {
+                p.write_lines(commands::wrap("&d", &message)).await;
+            }
+        }
+        Command::Who => {
+            let world = state.world.lock().await;
+            let cli = state.cli.get();
+            let name = world.name.as_ref().unwrap_or(&cli.name);
+            let mut online = players.values().collect::<Vec<_>>();
+            online.sort_by_key(|p| std::cmp::Reverse(p.rank));
+            let online = online
+                .iter()
+                .map(|p| format!("{}{}&e ({})", p.rank.color(), p.player_name.trim(), p.rank))
+                .collect::<Vec<_>>()
+                .join(", ");
+            sender
+                .reply_lines(commands::wrap(
+                    "&e",
+                    &format!("{name}&e ({}): {online}", players.len()),
+                ))
+                .await;
+        }
+        Command::Seen(name) => {
+            let message = if players
+                .values()
+                .any(|p| p.player_name.trim().eq_ignore_ascii_case(&name))
+            {
+                format!("&e{name} is online right now")
+            } else if let Some(since) = state.seen.lock().await.since(&name) {
+                format!(
+                    "&e{name} was last seen {} ago",
+                    commands::format_duration(since)
+                )
+            } else {
+                format!("&c{name} has never been here")
+            };
+            sender.reply(message).await;
+        }
+        Command::Kick(name, reason) => {
+            let Some(target) = players
+                .values()
+                .find(|p| p.player_name.trim().eq_ignore_ascii_case(&name))
+            else {
+                sender.reply(format!("&c{name} is not online")).await;
+                return;
+            };
+            if !can_punish(sender.rank, target.rank) {
+                sender.reply(format!("&cYou can not kick {name}")).await;
+                return;
+            }
+            let by = &sender.name;
+            info!("{by} kicked {name}");
+            let mut disconnect_reason = format!("Kicked by {by}");
+            if let Some(reason) = reason {
+                disconnect_reason.push_str(&format!(": {reason}"));
+            }
+            target
+                .c
+                .disconnect(Some(&DisconnectPlayer { disconnect_reason }))
+                .await;
+            for p in players.values() {
+                p.write_message(format!("&e{name} was kicked by {by}"))
+                    .await;
+            }
+        }
+        Command::Mute(name, duration) => {
+            let target = players
+                .values()
+                .find(|p| p.player_name.trim().eq_ignore_ascii_case(&name));
+            let rank = match target {
+                Some(t) => t.rank,
+                None => state.ranks.lock().await.get(&name),
+            };
+            if !can_punish(sender.rank, rank) {
+                sender.reply(format!("&cYou can not mute {name}")).await;
+                return;
+            }
+            info!("{} muted {name} for {duration:?}", sender.name);
+            state
+                .mutes
+                .lock()
+                .await
+                .insert(name.to_lowercase(), Instant::now() + duration);
+            let duration = commands::format_duration(duration);
+            if let Some(t) = target {
+                t.write_message(format!("&cYou are muted for {duration}"))
+                    .await;
+            }
+            sender
+                .reply(format!("&e{name} is muted for {duration}"))
+                .await;
+        }
+        Command::Unmute(name) => {
+            let message = match state.mutes.lock().await.remove(&name.to_lowercase()) {
+                Some(_) => format!("&e{name} can chat again"),
+                None => format!("&c{name} is not muted"),
+            };
+            sender.reply(message).await;
+        }
+        Command::Freeze(name) => {
+            let Some(target) = players
+                .values_mut()
+                .find(|p| p.player_name.trim().eq_ignore_ascii_case(&name))
+            else {
+                sender.reply(format!("&c{name} is not online")).await;
+                return;
+            };
+            let message = if !can_punish(sender.rank, target.rank) {
+                format!("&cYou can not freeze {name}")
+            } else {
+                target.frozen = !target.frozen;
+                let mut frozen = state.frozen.lock().await;
+                if target.frozen {
+                    frozen.insert(name.to_lowercase());
+                } else {
+                    frozen.remove(&name.to_lowercase());
+                }
+                info!("{} froze {name}: {}", sender.name, target.frozen);
+                if target.frozen {
+                    target.write_message("&cYou have been frozen".into()).await;
+                    format!("&e{name} is frozen")
+                } else {
+                    target.write_message("&eYou can move again".into()).await;
+                    format!("&e{name} can move again")
+                }
+            };
+            sender.reply(message).await;
+        }
+        Command::Summon(target) => {
+            let Some(player) = me else {
+                return;
+            };
+            let (x, y, z, yaw, pitch) = (player.x, player.y, player.z, player.yaw, player.pitch);
+            let mut found = false;
+            for (id, p) in players.iter_mut() {
+                let matches = match &target {
+                    Some(name) => p.player_name.trim().eq_ignore_ascii_case(name),
+                    None => Some(*id) != sender.id,
+                };
+                if matches {
+                    info!("{} summoned {id}", sender.name);
+                    p.teleport(x, y, z, yaw, pitch).await;
+                    found = true;
+                }
+            }
+            if let (Some(name), false) = (&target, found) {
+                sender.reply(format!("&c{name} is not online")).await;
+            }
+        }
+        Command::Spawn => {
+            let (x, y, z) = state.map.lock().await.spawn_point;
+            if let Some(player) = sender.id.and_then(|id| players.get_mut(&id)) {
+                player.teleport(x, y, z, 0, 0).await;
+            }
+        }
+        Command::SetSpawn => {
+            let Some(player) = sender.id.and_then(|id| players.get_mut(&id)) else {
+                return;
+            };
+            let (x, y, z) = (player.x, player.y, player.z);
+            info!("{} moved the spawn point to ({x}, {y}, {z})", sender.name);
+            *state.changed.lock().await = true;
+            state.map.lock().await.spawn_point = (x, y, z);
+            // Snap to the exact spawn point the server saved
+            player.teleport(x, y, z, player.yaw, player.pitch).await;
+            sender.reply("&eSpawn point set".into()).await;
+        }
+        Command::Home => {
+            let home = state.locations.lock().await.home(&sender.name);
+            match home {
+                Some(l) => {
+                    if let Some(player) = sender.id.and_then(|id| players.get_mut(&id)) {
+                        player.teleport(l.x, l.y, l.z, l.yaw, l.pitch).await;
+                    }
+                }
+                None => {
+                    sender
+                        .reply("&cYou have no home yet, see /sethome".into())
+                        .await;
+                }
+            }
+        }
+        Command::SetHome => {
+            let Some(player) = me else {
+                return;
+            };
+            state
+                .locations
+                .lock()
+                .await
+                .set_home(&sender.name, Location::of(player));
+            sender.reply("&eHome set".into()).await;
+        }
+        Command::Warp(None) => {
+            let locations = state.locations.lock().await;
+            let warps = locations
+                .warps()
+                .map(|w| w.as_str())
+                .collect::<Vec<_>>()
+                .join(", ");
+            sender
+                .reply_lines(commands::wrap("&e", &format!("Warps: {warps}")))
+                .await;
+        }
+        Command::Warp(Some(name)) => {
+            let warp = state.locations.lock().await.warp(&name);
+            match warp {
+                Some(l) => {
+                    if let Some(player) = sender.id.and_then(|id| players.get_mut(&id)) {
+                        player.teleport(l.x, l.y, l.z, l.yaw, l.pitch).await;
+                    }
+                }
+                None => {
+                    sender.reply(format!("&cThere is no warp {name}")).await;
+                }
+            }
+        }
+        Command::SetWarp(name) => {
+            let Some(player) = me else {
+                return;
+            };
+            info!("{} set the warp {name}", sender.name);
+            state
+                .locations
+                .lock()
+                .await
+                .set_warp(&name, Location::of(player));
+            sender.reply(format!("&eWarp {name} set")).await;
+        }
+        Command::DelWarp(name) => {
+            let message = if state.locations.lock().await.remove_warp(&name) {
+                info!("{} removed the warp {name}", sender.name);
+                format!("&eWarp {name} removed")
+            } else {
+                format!("&cThere is no warp {name}")
+            };
+            sender.reply(message).await;
+        }
+        Command::Motd(motd) => {
+            info!("{} changed the MOTD to {motd}", sender.name);
+            let mut world = state.world.lock().await;
+            world.motd = Some(motd);
+            let cli = state.cli.get();
+            world.save(&world::path(&cli.data));
+            for (_, p) in players.iter() {
+                if p.ext.has(cpe::INSTANT_MOTD) {
+                    p.write_identification(&cli, &world).await;
+                }
+            }
+            sender.reply("&eMOTD changed".into()).await;
+        }
+        Command::Cinematic(on, target) => {
+            let Some(name) = sender.target(target).await else {
+                return;
+            };
+            match players.values().find(|p| p.player_name.trim() == name) {
+                Some(t) if t.ext.has(cpe::CINEMATIC_GUI) => t.set_cinematic(on).await,
+                Some(_) => {
+                    sender
+                        .reply(format!("&c{name} does not support cinematics"))
+                        .await
+                }
+                None => sender.reply("&cCould not find that player".into()).await,
+            }
+        }
+        Command::Effect(name) => {
+            let Some(player) = me else {
+                return;
+            };
+            if let Some(id) = state.assets.effect_id(&name) {
+                let pos = (player.x, player.y, player.z);
+                let effect = assets::spawn_effect(id, pos, pos);
+                for (_, p) in players.iter() {
+                    if p.ext.has(cpe::CUSTOM_PARTICLES) {
+                        p.c.write_packet(&effect).await.unwrap();
+                    }
+                }
+            } else {
+                sender.reply(format!("&c`{name}` is not an effect")).await;
+            }
+        }
+        Command::Fill(block) => {
+            let Some(player) = me else {
+                return;
+            };
+            if let Selection::Done(cuboid) = &player.selection {
+                if cuboid.volume() > selection::MAX_FILL {
+                    sender
+                        .reply(format!(
+                            "&cCannot fill more than {} blocks at once",
+                            selection::MAX_FILL
+                        ))
+                        .await;
+                    return;
+                }
+                // The same checks as for placing a single block, for every block in the cuboid
+                let build_rank = state.world.lock().await.build_rank;
+                let can_build = |b| state.permissions.can_build(sender.rank, b);
+                let allowed = sender.rank >= build_rank && can_build(block) && {
+                    let map = state.map.lock().await;
+                    cuboid
+                        .positions()
+                        .all(|(x, y, z)| map.get_block(x, y, z).is_none_or(can_build))
+                };
+                if !allowed {
+                    sender
+                        .reply("&cYou are not allowed to change these blocks".into())
+                        .await;
+                    return;
+                }
+                *state.changed.lock().await = true;
+                let mut map = state.map.lock().await;
+                let mut changes = state.changes.lock().await;
+                for (x, y, z) in cuboid.positions() {
+                    if map.index_of(x, y, z).is_some() {
+                        map.set_block(x, y, z, block);
+                        changes.insert((x, y, z), block);
+                    }
+                }
+                info!(
+                    "{} filled {} blocks with {block}",
+                    sender.name,
+                    cuboid.volume()
+                );
+                metrics::BLOCK_CHANGES.add(cuboid.volume() as u64);
+            } else {
+                sender.reply("&cSelect an area first".into()).await;
+            }
+        }
+        Command::Hold(block) => {
+            let Some(player) = me else {
+                return;
+            };
+            if player.ext.has(cpe::HELD_BLOCK) {
+                player.hold(block, false).await;
+            } else {
+                sender
+                    .reply("&cYour client does not support holding blocks".into())
+                    .await;
+            }
+        }
+        Command::Model(model, target) => {
+            if !model::is_valid(&model) && !state.assets.has_model(&model) {
+                sender.reply(format!("&c`{model}` is not a model")).await;
+            } else {
+                change_appearance(sender, &mut players, target, |a| {
+                    a.model = Some(model).filter(|m| m != model::DEFAULT_MODEL);
+                })
+                .await;
+            }
+        }
+        Command::Scale(scale, target) => {
+            change_appearance(sender, &mut players, target, |a| a.set_scale(scale)).await;
+        }
+        Command::Rotate(rotation, target) => {
+            change_appearance(sender, &mut players, target, |a| a.set_rotation(rotation)).await;
+        }
+        Command::Tp(TpTarget::Player(other_p)) => {
+            if let Some((o_id, (x, y, z, yaw))) = players
+                .iter()
+                .find(|(_, p)| p.player_name.trim() == other_p)
+                .map(|(id, p)| (*id, (p.x, p.y, p.z, p.yaw)))
+            {
+                info!("{} teleported to {o_id}", sender.name);
+                if let Some(player) = sender.id.and_then(|id| players.get_mut(&id)) {
+                    player.teleport(x, y, z, yaw, 0).await;
+                }
+            } else {
+                debug!("{} tried to teleport to {other_p}", sender.name);
+                sender
+                    .reply(format!("&cCould not find player `{other_p}`"))
+                    .await;
+            }
+        }
+        Command::Tp(TpTarget::Position(x, y, z)) => {
+            let Some(player) = sender.id.and_then(|id| players.get_mut(&id)) else {
+                return;
+            };
+            let (x_size, y_size, z_size) = state.map.lock().await.size;
+            // Keep the player inside the map, standing on its top layer at most
+            let (x, y, z) = (
+                x.resolve(player.x.div_euclid(32))
+                    .clamp(0, i32::from(x_size) - 1),
+                y.resolve((player.y - PLAYER_HEIGHT).div_euclid(32))
+                    .clamp(0, y_size.into()),
+                z.resolve(player.z.div_euclid(32))
+                    .clamp(0, i32::from(z_size) - 1),
+            );
+            info!("{} teleported to ({x}, {y}, {z})", sender.name);
+            player
+                .teleport(
+                    x.saturating_mul(32).saturating_add(16),
+                    y.saturating_mul(32).saturating_add(PLAYER_HEIGHT),
+                    z.saturating_mul(32).saturating_add(16),
+                    player.yaw,
+                    player.pitch,
+                )
+                .await;
+        }
+        Command::Stop => {
+            info!("{} is stopping the server", sender.name);
+            sender.reply("&eStopping the server".into()).await;
+            state.stop.notify_one();
+        }
+        Command::Save => {
+            // Writing the whole map takes a while, players should keep moving and chatting
+            drop(players);
+            *state.changed.lock().await = false;
+            save_map(state.cli.get(), state.map.clone()).await;
+            info!("{} saved the map", sender.name);
+            sender.reply("&eMap saved".into()).await;
+        }
+        Command::Reload => {
+            let old = state.cli.get();
+            let restart = match state.cli.reload(&state.loader) {
+                Ok(restart) => restart,
+                Err(e) => {
+                    warn!("Could not reload the configuration: {e}");
+                    sender
+                        .reply_lines(commands::wrap("&c", &format!("Could not reload: {e}")))
+                        .await;
+                    return;
+                }
+            };
+            info!("{} reloaded the configuration", sender.name);
+            let cli = state.cli.get();
+            if cli.name != old.name || cli.motd != old.motd {
+                let world = state.world.lock().await;
+                for (_, p) in players.iter() {
+                    if p.ext.has(cpe::INSTANT_MOTD) {
+                        p.write_identification(&cli, &world).await;
+                    }
+                }
+            }
+            sender.reply("&eConfiguration reloaded".into()).await;
+            if !restart.is_empty() {
+                sender
+                    .reply_lines(commands::wrap(
+                        "&e",
+                        &format!("Restart to apply: {}", restart.join(", ")),
+                    ))
+                    .await;
+            }
+        }
+        Command::Say(message) => {
+            let message = format!("[{}] {message}", sender.name);
+            info!("{message}");
+            for p in players.values() {
+                p.write_lines(commands::wrap("&d", &message)).await;
+            }
+        }
+    }
+}
+
+/// Tells `name` for how much longer they are muted, if they are.
+async fn mute_message(name: &str, mutes: &Mutex<HashMap<String, Instant>>) -> Option<String> {
+    let left = mutes
+        .lock()
+        .await
+        .get(&name.trim().to_lowercase())?
+        .checked_duration_since(Instant::now())?;
+    Some(format!(
+        "&cYou are muted for another {}",
+        commands::format_duration(left)
+    ))
+}
+
+/// Changes the appearance of the named player, or of the sender if no name is given, and shows
+/// it to everyone.
+async fn change_appearance(
+    sender: &mut Sender,
+    players: &mut HashMap<i8, Player>,
+    target: Option<String>,
+    f: impl FnOnce(&mut Appearance),
+) {
+    let Some(name) = sender.target(target).await else {
+        return;
+    };
+    let Some((&target_id, target)) = players
+        .iter_mut()
+        .find(|(_, p)| p.player_name.trim() == name)
+    else {
+        sender
+            .reply(format!("&cCould not find player `{name}`"))
+            .await;
+        return;
+    };
+    f(&mut target.appearance);
+    let target = target.clone();
+    info!("{} changed the appearance of {target_id}", sender.name);
+    for (pid, p) in players.iter() {
+        let shown_id = if *pid == target_id { -1 } else { target_id };
+        p.write_appearance(shown_id, &target).await;
+    }
+}
+
+/// Kicks other sessions of `username` if configured to, returns whether the new session may log
+/// in.
+async fn handle_duplicate_login(
+    username: &str,
+    players: &mut HashMap<i8, Player>,
+    negotiations: &mut HashMap<i8, Negotiation>,
+    seen: &Mutex<LastSeen>,
+    cli: &Cli,
+) -> bool {
+    let is_same = |name: &str| name.trim().eq_ignore_ascii_case(username);
+    let online: Vec<i8> = players
+        .iter()
+        .filter(|(_, p)| is_same(&p.player_name))
+        .map(|(id, _)| *id)
+        .collect();
+    let negotiating: Vec<i8> = negotiations
+        .iter()
+        .filter(|(_, n)| is_same(&n.username))
+        .map(|(id, _)| *id)
+        .collect();
+    if online.is_empty() && negotiating.is_empty() {
+        return true;
+    }
+    if let DuplicateLogin::RejectNew = cli.duplicate_login {
+        return false;
+    }
+
+    let reason = DisconnectPlayer {
+        disconnect_reason: "Logged in from another location".into(),
+    };
+    for id in online {
+        if let Some(p) = players.remove(&id) {
+            info!("{id} was kicked because {username} logged in again");
+            p.c.disconnect(Some(&reason)).await;
+            leave(id, &p, players, seen, cli).await;
+        }
+    }
+    for id in negotiating {
+        if let Some(n) = negotiations.remove(&id) {
+            info!("{id} was kicked because {username} logged in again");
+            n.c.disconnect(Some(&reason)).await;
+        }
+    }
+    true
+}
+
+/// Records when a player left and removes them for everyone else, who are still in `players`.
+async fn leave(
+    id: i8,
+    player: &Player,
+    players: &HashMap<i8, Player>,
+    seen: &Mutex<LastSeen>,
+    cli: &Cli,
+) {
+    let name = player.player_name.trim();
+    seen.lock().await.record(name);
+    for (_, p) in players.iter() {
+        p.c.write_packet(&DespawnPlayer { player_id: id })
+            .await
+            .unwrap();
+        if p.ext.has(cpe::EXT_PLAYER_LIST) {
+            p.c.write_packet(&ExtRemovePlayerName { name_id: id.into() })
+                .await
+                .unwrap();
+        }
+    }
+    if let Some(message) = &cli.leave_message {
+        let message = message.replace("{player}", name);
+        for (_, p) in players.iter() {
+            p.write_message(message.clone()).await;
+        }
+    }
+}
+
+/// Sends a private message from the sender to the named player, who can answer with `/r`.
+async fn whisper(
+    sender: &mut Sender,
+    name: &str,
+    message: &str,
+    players: &mut HashMap<i8, Player>,
+) {
+    let Some(target) = players
+        .values_mut()
+        .find(|p| p.player_name.trim().eq_ignore_ascii_case(name))
+    else {
+        sender.reply(format!("&c{name} is not online")).await;
+        return;
+    };
+    debug!("{} whispered to {name}: {message}", sender.name);
+    let name = target.player_name.trim().to_string();
+    target.reply_to = Some(sender.name.clone());
+    target
+        .write_lines(commands::wrap(
+            "&7",
+            &format!("[{} -> you] {message}", sender.name),
+        ))
+        .await;
+    sender
+        .reply_lines(commands::wrap("&7", &format!("[you -> {name}] {message}")))
+        .await;
+}
+
+/// Whether a player of rank `by` may ban, kick or mute a player of rank `target`
+fn can_punish(by: Rank, target: Rank) -> bool {
+    by == Rank::Owner || target < by
+}
+
+/// Bans a name and disconnects the player using it.
+async fn ban_player(
+    sender: &mut Sender,
+    name: &str,
+    duration: Option<Duration>,
+    reason: Option<String>,
+    players: &HashMap<i8, Player>,
+    ranks: &Mutex<Ranks>,
+    bans: &std::sync::Mutex<Bans>,
+) {
+    if !can_punish(sender.rank, ranks.lock().await.get(name)) {
+        sender.reply(format!("&cYou can not ban {name}")).await;
+        return;
+    }
+    info!("{} banned {name}", sender.name);
+    let ban = Ban::new(&sender.name, reason, duration);
+    let reason = DisconnectPlayer {
+        disconnect_reason: ban.message(),
+    };
+    bans.lock().unwrap().ban_name(name, ban);
+    if let Some(p) = players
+        .values()
+        .find(|p| p.player_name.trim().eq_ignore_ascii_case(name))
+    {
+        p.c.disconnect(Some(&reason)).await;
+    }
+    sender.reply(format!("&e{name} is banned")).await;
+}
+
+/// Moves the named player one rank up or down, online or not.
+///
+/// Players can only change the rank of players below them and only to ranks below their own,
+/// except for owners.
+async fn change_rank(
+    sender: &mut Sender,
+    name: &str,
+    step: fn(Rank) -> Option<Rank>,
+    players: &mut HashMap<i8, Player>,
+    ranks: &Mutex<Ranks>,
+    world: &WorldSettings,
+) {
+    let mut ranks = ranks.lock().await;
+    let current = ranks.get(name);
+    let rank = sender.rank;
+    let allowed = |r| rank == Rank::Owner || r < rank;
+    if !allowed(current) {
+        sender
+            .reply(format!("&cYou can not change the rank of {name}"))
+            .await;
+        return;
+    }
+    let new = match step(current) {
+        Some(new) if allowed(new) => new,
+        _ => {
+            sender
+                .reply(format!("&c{name} can not become any other rank"))
+                .await;
+            return;
+        }
+    };
+    info!(
+        "{} changed the rank of {name} from {current} to {new}",
+        sender.name
+    );
+    ranks.set(name, new);
+    sender
+        .reply(format!("&e{name} is now {}{new}", new.color()))
+        .await;
+    if let Some(p) = players
+        .values_mut()
+        .find(|p| p.player_name.trim().eq_ignore_ascii_case(name))
+    {
+        p.set_rank(new, world.hacks_for(new)).await;
+        p.write_message(format!("&eYou are now {}{new}", new.color()))
+            .await;
+    }
+}
+
+/// Completes the login of a CPE client once all of its extensions are known.
+async fn finish_negotiation(
+    id: i8,
+    negotiations: &Mutex<HashMap<i8, Negotiation>>,
+    players: &Mutex<HashMap<i8, Player>>,
+    map: &Mutex<Terrain>,
+    cli: &Cli,
+    world: &Mutex<WorldSettings>,
+    assets: &Assets,
+) {
+    // Locked before the negotiation ends, so a login with the same name sees it in either map
+    let mut players = players.lock().await;
+    let n = {
+        let mut negotiations = negotiations.lock().await;
+        match negotiations.get(&id) {
+            Some(n) if n.is_complete() => negotiations.remove(&id).unwrap(),
+            _ => return,
+        }
+    };
+    let _ = n.tx.send(());
+    let ext = Extensions::from_entries(&n.entries);
+    if ext.has(cpe::EXTENDED_BLOCKS) {
+        n.c.enable_extended_blocks();
+    }
+    if ext.has(cpe::EXT_ENTITY_POSITIONS) {
+        n.c.enable_ext_entity_positions();
+    }
+    let world = world.lock().await.clone();
+    let mut player = Player::new(
+        n.c,
+        n.username,
+        n.addr,
+        ext,
+        n.rank,
+        world.hacks_for(n.rank),
+        map.lock().await.spawn_point,
+    );
+    player.frozen = n.frozen;
+    join(id, player, &mut players, map, cli, &world, assets).await;
+}
+
+/// Sends the level to a freshly identified player and spawns them for everyone.
+async fn join(
+    id: i8,
+    player: Player,
+    players: &mut HashMap<i8, Player>,
+    map: &Mutex<Terrain>,
+    cli: &Cli,
+    world: &WorldSettings,
+    assets: &Assets,
+) {
+    let c = player.c.clone();
+
+    if !player.ext.has(cpe::EXT_ENTITY_POSITIONS) {
+        let size = map.lock().await.size;
+        if size.0 > MAX_VANILLA_SIZE || size.1 > MAX_VANILLA_SIZE || size.2 > MAX_VANILLA_SIZE {
+            info!("{id} can not join because the map is too large for its client");
+            c.disconnect(Some(&DisconnectPlayer {
+                disconnect_reason: format!(
+                    "Map is too large, use a client with {}",
+                    cpe::EXT_ENTITY_POSITIONS
+                ),
+            }))
+            .await;
+            return;
+        }
+    }
+
+    let start = Instant::now();
+    let mut buf = vec![];
+    player.push_identification(&mut buf, cli, world);
+    player.push_level(&mut buf, &*map.lock().await);
+    if player.ext.has(cpe::CUSTOM_PARTICLES) {
+        for effect in assets.to_define_effects() {
+            push_packet(&mut buf, &effect);
+        }
+    }
+    if player.ext.has(cpe::CUSTOM_MODELS) {
+        for (model, parts) in assets.to_define_models() {
+            push_packet(&mut buf, &model);
+            for part in parts {
+                push_packet(&mut buf, &part);
+            }
+        }
+    }
+    if player.ext.has(cpe::TEXT_HOT_KEY) {
+        for hotkey in &world.hotkeys {
+            push_packet(&mut buf, &hotkey.to_set_text_hot_key());
+        }
+    }
+    let _ = c.write_bytes(buf).await;
+    metrics::MAP_SEND_TIME.observe(start.elapsed());
+
+    for (pid, p) in players.iter() {
+        p.write_spawn(id, &player).await;
+        player.write_spawn(*pid, p).await;
+        p.write_list_entry(id, &player).await;
+        player.write_list_entry(*pid, p).await;
+    }
+    player.write_spawn(-1, &player).await;
+    player.write_list_entry(id, &player).await;
+    let name = player.player_name.trim().to_string();
+    players.insert(id, player);
+    if let Some(message) = &cli.join_message {
+        let message = message.replace("{player}", &name);
+        for (_, p) in players.iter() {
+            p.write_message(message.clone()).await;
+        }
+    }
+}
+
+/// Sends several messages which arrive in order.
+async fn write_lines(c: &ClientController, lines: Vec<String>) {
+    let mut buf = vec![];
+    for mut message in lines {
+        message.truncate(64);
+        push_packet(
+            &mut buf,
+            &Message {
+                player_id: 0,
+                message,
+            },
+        );
+    }
+    let _ = c.write_bytes(buf).await;
+}
+
+/// Appends a packet to `buf` so multiple packets can be written in order using
+/// [`ClientController::write_bytes`].
+fn push_packet<T: Serialize + Packet>(buf: &mut Vec<u8>, p: &T) {
+    classicl::push_packet(buf, p).unwrap();
+}
+
+fn to_fixed_point(v: f64) -> i32 {
+    (v * 32.0).round() as i32
+}
+
+/// Clamps a fixed-point coordinate to the range vanilla clients understand.
+fn to_short(v: i32) -> i16 {
+    v.clamp(i16::MIN.into(), i16::MAX.into()) as i16
 }
 
 async fn save_map(cli: Arc<Cli>, map: Arc<Mutex<Terrain>>) {
+    let start = Instant::now();
     let map = map.lock().await;
-    let data = bincode::serialize(&*map).unwrap();
+    let data = map.save().unwrap();
     let path = generate_path(&cli.data);
 
     let mut file = File::create(path).unwrap();
     file.write_all(&data).unwrap();
+    metrics::SAVE_TIME.observe(start.elapsed());
 }
 
 fn generate_path(path: &Path) -> PathBuf {
@@ -0,0 +1,280 @@
+/* This file is part of classicl-server.
+ *
+ * classicl-server is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    fmt::Write,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use classicl::stats::STATS;
+
+/// Upper bounds of the histogram buckets in seconds
+const BUCKETS: [f64; 12] = [
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0,
+];
+
+/// Identification packets, whether the login succeeds or not
+pub static IDENTIFICATIONS: Counter = Counter::new();
+/// Blocks changed by players and commands
+pub static BLOCK_CHANGES: Counter = Counter::new();
+pub static SAVE_TIME: Histogram = Histogram::new();
+/// Time to prepare and queue the level for a joining player
+pub static MAP_SEND_TIME: Histogram = Histogram::new();
+static HANDLER_TIMES: [Histogram; Handler::ALL.len()] =
+    [const { Histogram::new() }; Handler::ALL.len()];
+
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    const fn new() -> Self {
+        Self(AtomicU64::new(0))
+    }
+
+    pub fn add(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+pub struct Histogram {
+    /// Observations per bucket, the last one being for everything above the largest bound
+    counts: [AtomicU64; BUCKETS.len() + 1],
+    sum_nanos: AtomicU64,
+}
+
+impl Histogram {
+    const fn new() -> Self {
+        Self {
+            counts: [const { AtomicU64::new(0) }; BUCKETS.len() + 1],
+            sum_nanos: AtomicU64::new(0),
+        }
+    }
+
+    pub fn observe(&self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        let bucket = BUCKETS
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(BUCKETS.len());
+        self.counts[bucket].fetch_add(1, Ordering::Relaxed);
+        self.sum_nanos
+            .fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// Writes the buckets, sum and count of the histogram, `labels` being empty or ending with a
+    /// comma.
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let mut count = 0;
+        for (bound, bucket) in BUCKETS.iter().zip(&self.counts) {
+            count += bucket.load(Ordering::Relaxed);
+            writeln!(out, "{name}_bucket{{{labels}le=\"{bound}\"}} {count}").unwrap();
+        }
+        count += self.counts[BUCKETS.len()].load(Ordering::Relaxed);
+        writeln!(out, "{name}_bucket{{{labels}le=\"+Inf\"}} {count}").unwrap();
+        let sum = self.sum_nanos.load(Ordering::Relaxed) as f64 / 1e9;
+        let labels = match labels.trim_end_matches(',') {
+            "" => String::new(),
+            labels => format!("{{{labels}}}"),
+        };
+        writeln!(out, "{name}_sum{labels} {sum}").unwrap();
+        writeln!(out, "{name}_count{labels} {count}").unwrap();
+    }
+}
+
+/// Packet handlers whose time per packet is measured
+#[derive(Clone, Copy)]
+pub enum Handler {
+    Identification,
+    ExtInfo,
+    ExtEntry,
+    SetBlock,
+    PositionOrientation,
+    PlayerClick,
+    TwoWayPing,
+    Message,
+}
+
+impl Handler {
+    const ALL: [Self; 8] = [
+        Self::Identification,
+        Self::ExtInfo,
+        Self::ExtEntry,
+        Self::SetBlock,
+        Self::PositionOrientation,
+        Self::PlayerClick,
+        Self::TwoWayPing,
+        Self::Message,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Identification => "identification",
+            Self::ExtInfo => "ext_info",
+            Self::ExtEntry => "ext_entry",
+            Self::SetBlock => "set_block",
+            Self::PositionOrientation => "position_orientation",
+            Self::PlayerClick => "player_click",
+            Self::TwoWayPing => "two_way_ping",
+            Self::Message => "message",
+        }
+    }
+}
+
+/// Measures the time until it is dropped.
+pub struct Timer {
+    start: Instant,
+    histogram: &'static Histogram,
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        self.histogram.observe(self.start.elapsed());
+    }
+}
+
+/// Starts measuring how long handling a packet takes.
+pub fn time_handler(handler: Handler) -> Timer {
+    Timer {
+        start: Instant::now(),
+        histogram: &HANDLER_TIMES[handler as usize],
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(out, "# HELP {name} {help}").unwrap();
+    writeln!(out, "# TYPE {name} {kind}").unwrap();
+}
+
+/// Renders every metric in the Prometheus text format.
+pub fn render(players: usize) -> String {
+    let mut out = String::new();
+
+    header(
+        &mut out,
+        "classicl_connected_clients",
+        "gauge",
+        "Clients connected right now",
+    );
+    writeln!(out, "classicl_connected_clients {}", STATS.connected()).unwrap();
+    header(
+        &mut out,
+        "classicl_players",
+        "gauge",
+        "Players in the world right now",
+    );
+    writeln!(out, "classicl_players {players}").unwrap();
+    header(
+        &mut out,
+        "classicl_connections_total",
+        "counter",
+        "Clients which got an id",
+    );
+    writeln!(out, "classicl_connections_total {}", STATS.connections()).unwrap();
+    header(
+        &mut out,
+        "classicl_refused_connections_total",
+        "counter",
+        "Connections refused because of bans or the player limit",
+    );
+    writeln!(
+        out,
+        "classicl_refused_connections_total {}",
+        STATS.refused()
+    )
+    .unwrap();
+    header(
+        &mut out,
+        "classicl_identifications_total",
+        "counter",
+        "Login attempts",
+    );
+    writeln!(
+        out,
+        "classicl_identifications_total {}",
+        IDENTIFICATIONS.get()
+    )
+    .unwrap();
+
+    header(
+        &mut out,
+        "classicl_packets_received_total",
+        "counter",
+        "Packets received by id",
+    );
+    for (id, count) in STATS.packets_received() {
+        writeln!(
+            out,
+            "classicl_packets_received_total{{id=\"{id}\"}} {count}"
+        )
+        .unwrap();
+    }
+    header(
+        &mut out,
+        "classicl_packets_sent_total",
+        "counter",
+        "Packets sent by id",
+    );
+    for (id, count) in STATS.packets_sent() {
+        writeln!(out, "classicl_packets_sent_total{{id=\"{id}\"}} {count}").unwrap();
+    }
+    header(
+        &mut out,
+        "classicl_sent_bytes_total",
+        "counter",
+        "Bytes written to clients",
+    );
+    writeln!(out, "classicl_sent_bytes_total {}", STATS.bytes_sent()).unwrap();
+    header(
+        &mut out,
+        "classicl_block_changes_total",
+        "counter",
+        "Blocks changed by players and commands",
+    );
+    writeln!(out, "classicl_block_changes_total {}", BLOCK_CHANGES.get()).unwrap();
+
+    header(
+        &mut out,
+        "classicl_save_duration_seconds",
+        "histogram",
+        "Time to save the map",
+    );
+    SAVE_TIME.render(&mut out, "classicl_save_duration_seconds", "");
+    header(
+        &mut out,
+        "classicl_map_send_duration_seconds",
+        "histogram",
+        "Time to prepare and queue the level for a joining player",
+    );
+    MAP_SEND_TIME.render(&mut out, "classicl_map_send_duration_seconds", "");
+    header(
+        &mut out,
+        "classicl_handler_duration_seconds",
+        "histogram",
+        "Time to handle a packet by handler",
+    );
+    for (handler, histogram) in Handler::ALL.iter().zip(&HANDLER_TIMES) {
+        histogram.render(
+            &mut out,
+            "classicl_handler_duration_seconds",
+            &format!("handler=\"{}\",", handler.name()),
+        );
+    }
+    out
+}
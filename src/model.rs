@@ -0,0 +1,95 @@
+/* This file is part of classicl-server.
+ *
+ * classicl-server is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use classicl::server::{ChangeModel, EntityProperty};
+
+/// Model used when none is set
+pub const DEFAULT_MODEL: &str = "humanoid";
+
+/// Entity models known to ClassiCube, besides block ids
+const MODELS: &[&str] = &[
+    DEFAULT_MODEL,
+    "chicken",
+    "creeper",
+    "pig",
+    "sheep",
+    "sheep_nofur",
+    "skeleton",
+    "spider",
+    "zombie",
+    "sit",
+    "head",
+    "giant",
+    "corpse",
+    "none",
+];
+
+/// Checks whether `model` is a known model or a block id.
+pub fn is_valid(model: &str) -> bool {
+    MODELS.contains(&model) || model.parse::<u16>().is_ok_and(|id| id <= 767)
+}
+
+/// Rotation in degrees around the X, Y and Z axis, [`EntityProperty::property_type`] 0 to 2
+const ROTATION: [u8; 3] = [0, 1, 2];
+/// Scale along the X, Y and Z axis, [`EntityProperty::property_type`] 3 to 5
+const SCALE: [u8; 3] = [3, 4, 5];
+/// Scale values are sent multiplied by this
+const SCALE_FACTOR: f64 = 1000.0;
+
+/// How a player is shown to others
+#[derive(Clone, Debug, Default)]
+pub struct Appearance {
+    /// Name of the model or a block id, the default model if `None`
+    pub model: Option<String>,
+    /// Values of the changed entity properties indexed by their type
+    pub properties: [Option<i32>; 6],
+}
+
+impl Appearance {
+    pub fn set_rotation(&mut self, (x, y, z): (i32, i32, i32)) {
+        for (t, v) in ROTATION.into_iter().zip([x, y, z]) {
+            self.properties[t as usize] = Some(v);
+        }
+    }
+
+    pub fn set_scale(&mut self, scale: f64) {
+        for t in SCALE {
+            self.properties[t as usize] = Some((scale * SCALE_FACTOR).round() as i32);
+        }
+    }
+
+    pub fn to_change_model(&self, id: i8) -> ChangeModel {
+        ChangeModel {
+            entity_id: id,
+            model: self.model.as_deref().unwrap_or(DEFAULT_MODEL).into(),
+        }
+    }
+
+    /// Properties which were changed from their default values
+    pub fn to_entity_properties(&self, id: i8) -> Vec<EntityProperty> {
+        self.properties
+            .iter()
+            .enumerate()
+            .filter_map(|(t, v)| {
+                v.map(|v| EntityProperty {
+                    entity_id: id,
+                    property_type: t as u8,
+                    property_value: v,
+                })
+            })
+            .collect()
+    }
+}
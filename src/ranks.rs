@@ -0,0 +1,245 @@
+/* This file is part of classicl-server.
+ *
+ * classicl-server is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::{commands, terrain::blocks};
+
+/// [`classicl::server::ServerIdentification::user_type`] of operators
+const OP_USER_TYPE: u8 = 0x64;
+
+/// Location of the player ranks inside the data directory
+pub fn path(data: &Path) -> PathBuf {
+    data.join("ranks.toml")
+}
+
+/// Location of the rank permissions inside the data directory
+pub fn permissions_path(data: &Path) -> PathBuf {
+    data.join("permissions.toml")
+}
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+    ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Rank {
+    #[default]
+    Guest,
+    Builder,
+    Moderator,
+    Admin,
+    Owner,
+}
+
+impl Rank {
+    const ALL: [Rank; 5] = [
+        Self::Guest,
+        Self::Builder,
+        Self::Moderator,
+        Self::Admin,
+        Self::Owner,
+    ];
+
+    pub fn next(self) -> Option<Self> {
+        Self::ALL.get(self as usize + 1).copied()
+    }
+
+    pub fn previous(self) -> Option<Self> {
+        Self::ALL.get((self as usize).checked_sub(1)?).copied()
+    }
+
+    /// Operators are allowed to break bedrock in their client.
+    pub fn is_op(self) -> bool {
+        self >= Self::Moderator
+    }
+
+    pub fn user_type(self) -> u8 {
+        if self.is_op() {
+            OP_USER_TYPE
+        } else {
+            0x00
+        }
+    }
+
+    /// Color code used in front of names
+    pub fn color(self) -> &'static str {
+        match self {
+            Self::Guest => "&7",
+            Self::Builder => "&f",
+            Self::Moderator => "&a",
+            Self::Admin => "&c",
+            Self::Owner => "&4",
+        }
+    }
+}
+
+impl Display for Rank {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Guest => "guest",
+            Self::Builder => "builder",
+            Self::Moderator => "moderator",
+            Self::Admin => "admin",
+            Self::Owner => "owner",
+        })
+    }
+}
+
+impl FromStr for Rank {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|r| r.to_string().eq_ignore_ascii_case(s))
+            .ok_or(())
+    }
+}
+
+/// Ranks given to players, saved in `ranks.toml` inside the data directory
+///
+/// There is no owner initially, the first one has to be added to this file while the server is
+/// stopped, e.g. `players = { notch = "owner" }`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Ranks {
+    #[serde(skip)]
+    path: PathBuf,
+    /// Rank of everyone not listed
+    #[serde(skip)]
+    default: Rank,
+    /// Ranks by lowercase player name
+    players: BTreeMap<String, Rank>,
+}
+
+impl Ranks {
+    pub fn load(path: &Path) -> Self {
+        let mut ranks: Self = if let Ok(s) = fs::read_to_string(path) {
+            toml::from_str(&s).unwrap()
+        } else {
+            Self::default()
+        };
+        ranks.path = path.to_path_buf();
+        ranks
+    }
+
+    pub fn get(&self, name: &str) -> Rank {
+        self.players
+            .get(&name.to_lowercase())
+            .copied()
+            .unwrap_or(self.default)
+    }
+
+    pub fn set_default(&mut self, rank: Rank) {
+        self.default = rank;
+    }
+
+    /// Changes the rank of a player and saves all ranks.
+    ///
+    /// The rank is kept even if it equals the default, so it stays the same when the default
+    /// changes.
+    pub fn set(&mut self, name: &str, rank: Rank) {
+        self.players.insert(name.to_lowercase(), rank);
+        fs::write(&self.path, toml::to_string_pretty(self).unwrap()).unwrap();
+    }
+}
+
+/// Lowest ranks allowed to use commands and blocks, editable in `permissions.toml` inside the data
+/// directory
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Permissions {
+    /// Rank needed by commands, by their name
+    pub commands: BTreeMap<String, Rank>,
+    /// Rank needed to place or break blocks, by their id
+    ///
+    /// The ids are strings because TOML keys can not be numbers.
+    pub blocks: BTreeMap<String, Rank>,
+}
+
+impl Default for Permissions {
+    fn default() -> Self {
+        let blocks = [
+            blocks::BEDROCK,
+            blocks::WATER,
+            blocks::STILL_WATER,
+            blocks::LAVA,
+            blocks::STILL_LAVA,
+        ];
+        Self {
+            commands: commands::COMMANDS
+                .iter()
+                .map(|c| (c.name.to_string(), c.rank))
+                .collect(),
+            blocks: blocks
+                .into_iter()
+                .map(|b| (b.to_string(), Rank::Moderator))
+                .collect(),
+        }
+    }
+}
+
+impl Permissions {
+    /// Loads the permissions from `path` and writes them back with the defaults of commands and
+    /// blocks added since, so new commands are not open to everyone.
+    pub fn load(path: &Path) -> Self {
+        let mut permissions: Self = if let Ok(s) = fs::read_to_string(path) {
+            toml::from_str(&s).unwrap()
+        } else {
+            Self::default()
+        };
+        let defaults = Self::default();
+        for (command, rank) in defaults.commands {
+            permissions.commands.entry(command).or_insert(rank);
+        }
+        for (block, rank) in defaults.blocks {
+            permissions.blocks.entry(block).or_insert(rank);
+        }
+        fs::write(path, toml::to_string_pretty(&permissions).unwrap()).unwrap();
+        permissions
+    }
+
+    /// Commands without an entry can be used by everyone.
+    pub fn can_use(&self, rank: Rank, command: &str) -> bool {
+        self.commands.get(command).is_none_or(|&r| rank >= r)
+    }
+
+    /// Blocks without an entry can be used by everyone.
+    pub fn can_build(&self, rank: Rank, block: u16) -> bool {
+        self.blocks
+            .get(&block.to_string())
+            .is_none_or(|&r| rank >= r)
+    }
+}
@@ -0,0 +1,209 @@
+/* This file is part of classicl-server.
+ *
+ * classicl-server is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+use log::{debug, info, warn};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::{console, execute, secret, Sender, State};
+
+// Packet types of the Source RCON protocol
+const AUTH: i32 = 3;
+const AUTH_RESPONSE: i32 = 2;
+const EXEC_COMMAND: i32 = 2;
+const RESPONSE_VALUE: i32 = 0;
+
+/// Largest packet accepted from clients, without the size field
+const MAX_PACKET_SIZE: i32 = 4096;
+/// Longest body of a single response packet, longer output is split
+const MAX_BODY: usize = 4000;
+
+/// Location of the RCON password inside the data directory
+pub fn password_path(data: &Path) -> PathBuf {
+    data.join("rcon_password")
+}
+
+/// Accepts RCON connections on `address` and runs their commands like the console does.
+pub async fn run(address: String, password: String, state: State) {
+    let listener = match TcpListener::bind(&address).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            warn!("Could not listen for RCON on {address}: {e}");
+            return;
+        }
+    };
+    info!("Listening for RCON on {address}");
+    loop {
+        let Ok((stream, addr)) = listener.accept().await else {
+            continue;
+        };
+        let password = password.clone();
+        let state = state.clone();
+        tokio::spawn(async move {
+            if let Err(e) = serve(stream, addr, &password, &state).await {
+                debug!("RCON connection of {addr} ended: {e}");
+            }
+        });
+    }
+}
+
+struct RconPacket {
+    id: i32,
+    kind: i32,
+    body: String,
+}
+
+async fn serve(
+    mut stream: TcpStream,
+    addr: SocketAddr,
+    password: &str,
+    state: &State,
+) -> io::Result<()> {
+    let mut authenticated = false;
+    loop {
+        let packet = read_packet(&mut stream).await?;
+        match packet.kind {
+            AUTH => {
+                authenticated = secret::matches(packet.body.as_bytes(), password.as_bytes());
+                if !authenticated {
+                    warn!("{addr} used a wrong RCON password");
+                    return write_packet(&mut stream, -1, AUTH_RESPONSE, "").await;
+                }
+                info!("{addr} logged into RCON");
+                write_packet(&mut stream, packet.id, AUTH_RESPONSE, "").await?;
+            }
+            EXEC_COMMAND if authenticated => {
+                let text = packet.body.trim();
+                info!("{addr} ran `{text}` over RCON");
+                let mut sender = Sender::console(console::NAME);
+                execute(&mut sender, text.strip_prefix('/').unwrap_or(text), state).await;
+                let output = sender
+                    .take_lines()
+                    .iter()
+                    .map(|line| console::strip_colors(line))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                for body in split_body(&output) {
+                    write_packet(&mut stream, packet.id, RESPONSE_VALUE, body).await?;
+                }
+            }
+            _ => return write_packet(&mut stream, -1, AUTH_RESPONSE, "").await,
+        }
+    }
+}
+
+async fn read_packet(stream: &mut (impl AsyncRead + Unpin)) -> io::Result<RconPacket> {
+    let size = stream.read_i32_le().await?;
+    if !(10..=MAX_PACKET_SIZE).contains(&size) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid packet size {size}"),
+        ));
+    }
+    let id = stream.read_i32_le().await?;
+    let kind = stream.read_i32_le().await?;
+    let mut body = vec![0; size as usize - 8];
+    stream.read_exact(&mut body).await?;
+    // The body is followed by a null byte and an empty string
+    let end = body.iter().position(|b| *b == 0).unwrap_or(body.len());
+    Ok(RconPacket {
+        id,
+        kind,
+        body: String::from_utf8_lossy(&body[..end]).into_owned(),
+    })
+}
+
+async fn write_packet(
+    stream: &mut (impl AsyncWrite + Unpin),
+    id: i32,
+    kind: i32,
+    body: &str,
+) -> io::Result<()> {
+    let mut buf = Vec::with_capacity(body.len() + 14);
+    buf.extend_from_slice(&(body.len() as i32 + 10).to_le_bytes());
+    buf.extend_from_slice(&id.to_le_bytes());
+    buf.extend_from_slice(&kind.to_le_bytes());
+    buf.extend_from_slice(body.as_bytes());
+    buf.extend_from_slice(&[0, 0]);
+    stream.write_all(&buf).await
+}
+
+/// Splits a response into bodies of at most [`MAX_BODY`] bytes without breaking characters.
+fn split_body(mut output: &str) -> Vec<&str> {
+    let mut bodies = vec![];
+    while output.len() > MAX_BODY {
+        let mut end = MAX_BODY;
+        while !output.is_char_boundary(end) {
+            end -= 1;
+        }
+        let (body, rest) = output.split_at(end);
+        bodies.push(body);
+        output = rest;
+    }
+    bodies.push(output);
+    bodies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn packets_survive_a_round_trip() {
+        let mut buf = vec![];
+        write_packet(&mut buf, 7, EXEC_COMMAND, "say hi")
+            .await
+            .unwrap();
+        assert_eq!(&buf[..4], &16i32.to_le_bytes());
+        assert_eq!(&buf[buf.len() - 2..], &[0, 0]);
+
+        let packet = read_packet(&mut buf.as_slice()).await.unwrap();
+        assert_eq!((packet.id, packet.kind), (7, EXEC_COMMAND));
+        assert_eq!(packet.body, "say hi");
+    }
+
+    #[tokio::test]
+    async fn bad_sizes_are_rejected() {
+        for size in [9, MAX_PACKET_SIZE + 1, -1] {
+            let mut buf = size.to_le_bytes().to_vec();
+            buf.resize(64, 0);
+            let error = read_packet(&mut buf.as_slice()).await.err().unwrap();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+        // Shorter than announced
+        let mut buf = vec![];
+        write_packet(&mut buf, 1, AUTH, "password").await.unwrap();
+        buf.truncate(buf.len() - 3);
+        assert!(read_packet(&mut buf.as_slice()).await.is_err());
+    }
+
+    #[test]
+    fn long_bodies_are_split_at_characters() {
+        assert_eq!(split_body(""), [""]);
+        let output = "ä".repeat(MAX_BODY);
+        let bodies = split_body(&output);
+        assert!(bodies.iter().all(|b| b.len() <= MAX_BODY));
+        assert_eq!(bodies.concat(), output);
+    }
+}
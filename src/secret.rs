@@ -0,0 +1,63 @@
+/* This file is part of classicl-server.
+ *
+ * classicl-server is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::Path,
+};
+
+use log::info;
+use rand::{distributions::Alphanumeric, Rng};
+
+const LENGTH: usize = 24;
+
+/// Reads a password or token from `path`, creating a random one if there is none yet.
+pub fn load(path: &Path) -> io::Result<String> {
+    if let Some(secret) = fs::read_to_string(path)
+        .ok()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+    {
+        return Ok(secret);
+    }
+    let secret: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(LENGTH)
+        .map(char::from)
+        .collect();
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    // Nobody but the user running the server should be able to read it, not even briefly
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(secret.as_bytes())?;
+    info!("Created a new secret in {}", path.display());
+    Ok(secret)
+}
+
+/// Compares every byte so the time taken does not tell how much of a guess was right.
+pub fn matches(guess: &[u8], secret: &[u8]) -> bool {
+    guess.len() == secret.len()
+        && guess
+            .iter()
+            .zip(secret)
+            .fold(0, |diff, (x, y)| diff | (x ^ y))
+            == 0
+}
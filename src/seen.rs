@@ -0,0 +1,78 @@
+/* This file is part of classicl-server.
+ *
+ * classicl-server is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Location of the last-seen times inside the data directory
+pub fn path(data: &Path) -> PathBuf {
+    data.join("seen.toml")
+}
+
+/// When players left the server, saved in `seen.toml` inside the data directory
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LastSeen {
+    #[serde(skip)]
+    path: PathBuf,
+    /// Whether there are times which are not saved yet
+    #[serde(skip)]
+    changed: bool,
+    /// Seconds since the Unix epoch by lowercase player name
+    players: BTreeMap<String, u64>,
+}
+
+impl LastSeen {
+    pub fn load(path: &Path) -> Self {
+        let mut seen: Self = if let Ok(s) = fs::read_to_string(path) {
+            toml::from_str(&s).unwrap()
+        } else {
+            Self::default()
+        };
+        seen.path = path.to_path_buf();
+        seen
+    }
+
+    /// Remembers that `name` is leaving now, written to disk by the next [`Self::save`].
+    pub fn record(&mut self, name: &str) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.players.insert(name.to_lowercase(), now);
+        self.changed = true;
+    }
+
+    /// Saves all times if some changed since the last save.
+    pub fn save(&mut self) {
+        if self.changed {
+            fs::write(&self.path, toml::to_string_pretty(self).unwrap()).unwrap();
+            self.changed = false;
+        }
+    }
+
+    /// How long ago `name` left, if they have been here before
+    pub fn since(&self, name: &str) -> Option<Duration> {
+        let seen = UNIX_EPOCH + Duration::from_secs(*self.players.get(&name.to_lowercase())?);
+        Some(SystemTime::now().duration_since(seen).unwrap_or_default())
+    }
+}
@@ -0,0 +1,118 @@
+/* This file is part of classicl-server.
+ *
+ * classicl-server is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use classicl::server::{MakeSelection, RemoveSelection};
+
+/// Id used for the selection a player is currently making
+const SELECTION_ID: u8 = 0;
+const COLOR: (i16, i16, i16, i16) = (0x40, 0xa0, 0xff, 0x60);
+/// Largest number of blocks `/fill` changes at once
+pub const MAX_FILL: i64 = 64 * 64 * 64;
+
+/// Region a player marks by placing or breaking two blocks
+#[derive(Clone, Debug, Default)]
+pub enum Selection {
+    #[default]
+    None,
+    /// The player is about to mark the first corner
+    Marking,
+    /// The first corner is marked
+    Marked((i16, i16, i16)),
+    Done(Cuboid),
+}
+
+impl Selection {
+    pub fn is_marking(&self) -> bool {
+        matches!(self, Self::Marking | Self::Marked(_))
+    }
+
+    /// Marks a corner, returns the finished cuboid when this was the second one.
+    pub fn mark(&mut self, pos: (i16, i16, i16)) -> Option<Cuboid> {
+        match self {
+            Self::Marking => {
+                *self = Self::Marked(pos);
+                None
+            }
+            Self::Marked(first) => {
+                let cuboid = Cuboid::new(*first, pos);
+                *self = Self::Done(cuboid.clone());
+                Some(cuboid)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Block aligned cuboid including both of its corners
+#[derive(Clone, Debug)]
+pub struct Cuboid {
+    pub min: (i16, i16, i16),
+    pub max: (i16, i16, i16),
+}
+
+impl Cuboid {
+    pub fn new(a: (i16, i16, i16), b: (i16, i16, i16)) -> Self {
+        Self {
+            min: (a.0.min(b.0), a.1.min(b.1), a.2.min(b.2)),
+            max: (a.0.max(b.0), a.1.max(b.1), a.2.max(b.2)),
+        }
+    }
+
+    pub fn size(&self) -> (i32, i32, i32) {
+        (
+            self.max.0 as i32 - self.min.0 as i32 + 1,
+            self.max.1 as i32 - self.min.1 as i32 + 1,
+            self.max.2 as i32 - self.min.2 as i32 + 1,
+        )
+    }
+
+    pub fn volume(&self) -> i64 {
+        let (x, y, z) = self.size();
+        x as i64 * y as i64 * z as i64
+    }
+
+    /// Every block position inside the cuboid
+    pub fn positions(&self) -> impl Iterator<Item = (i16, i16, i16)> {
+        let (min, max) = (self.min, self.max);
+        (min.1..=max.1).flat_map(move |y| {
+            (min.2..=max.2).flat_map(move |z| (min.0..=max.0).map(move |x| (x, y, z)))
+        })
+    }
+
+    pub fn to_make_selection(&self) -> MakeSelection {
+        MakeSelection {
+            selection_id: SELECTION_ID,
+            label: "Selection".into(),
+            start_x: self.min.0,
+            start_y: self.min.1,
+            start_z: self.min.2,
+            // The end is exclusive for the client
+            end_x: self.max.0.saturating_add(1),
+            end_y: self.max.1.saturating_add(1),
+            end_z: self.max.2.saturating_add(1),
+            red: COLOR.0,
+            green: COLOR.1,
+            blue: COLOR.2,
+            opacity: COLOR.3,
+        }
+    }
+}
+
+pub fn remove_selection() -> RemoveSelection {
+    RemoveSelection {
+        selection_id: SELECTION_ID,
+    }
+}
@@ -14,6 +14,13 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+/// Sent instead of blocks a client does not know
+pub const FALLBACK: u8 = STONE;
+/// Highest block id of clients without ExtendedBlocks
+pub const MAX_ID: u16 = u8::MAX as u16;
+/// Highest block id with ExtendedBlocks, which adds two bits to every id
+pub const MAX_EXTENDED_ID: u16 = 767;
+
 pub const AIR: u8 = 0;
 pub const STONE: u8 = 1;
 pub const GRASS: u8 = 2;
@@ -21,14 +28,14 @@ pub const DIRT: u8 = 3;
 /*
 pub const COBBLESTONE: u8 = 4;
 pub const WOOD: u8 = 5;
+*/
 pub const SAPLING: u8 = 6;
 pub const BEDROCK: u8 = 7;
-*/
 pub const WATER: u8 = 8;
-/*
 pub const STILL_WATER: u8 = 9;
 pub const LAVA: u8 = 10;
 pub const STILL_LAVA: u8 = 11;
+/*
 pub const SAND: u8 = 12;
 pub const GRAVEL: u8 = 13;
 */
@@ -56,26 +63,32 @@ pub const PINK: u8 = 33;
 pub const BLACK: u8 = 34;
 pub const GRAY: u8 = 35;
 pub const WHITE: u8 = 36;
-pub const DANDELION: u8 = 37;
 */
+pub const DANDELION: u8 = 37;
 pub const ROSE: u8 = 38;
-/*
 pub const BROWN_MUSHROOM: u8 = 39;
 pub const RED_MUSHROOM: u8 = 40;
+/*
 pub const GOLD: u8 = 41;
 pub const IRON: u8 = 42;
 pub const DOUBLE_SLAB: u8 = 43;
+*/
 pub const SLAB: u8 = 44;
+/*
 pub const BRICK: u8 = 45;
 pub const TNT: u8 = 46;
 pub const BOOKSHELF: u8 = 47;
 pub const MOSSY_ROCKS: u8 = 48;
 pub const OBSIDIAN: u8 = 49;
+*/
 pub const COBBLESTONE_SLAB: u8 = 50;
 pub const ROPE: u8 = 51;
+/*
 pub const SANDSTONE: u8 = 52;
+*/
 pub const SNOW: u8 = 53;
 pub const FIRE: u8 = 54;
+/*
 pub const LIGHT_PINK: u8 = 55;
 pub const FOREST_GREEN: u8 = 56;
 pub const BROWN: u8 = 57;
@@ -88,3 +101,109 @@ pub const PILLAR: u8 = 63;
 pub const CRATE: u8 = 64;
 pub const STONE_BRICK: u8 = 65;
 */
+
+/// Names of the default blocks, indexed by id
+const NAMES: [&str; 66] = [
+    "air",
+    "stone",
+    "grass",
+    "dirt",
+    "cobblestone",
+    "wood",
+    "sapling",
+    "bedrock",
+    "water",
+    "still_water",
+    "lava",
+    "still_lava",
+    "sand",
+    "gravel",
+    "gold_ore",
+    "iron_ore",
+    "coal_ore",
+    "log",
+    "leaves",
+    "sponge",
+    "glass",
+    "red",
+    "orange",
+    "yellow",
+    "lime",
+    "green",
+    "teal",
+    "aqua",
+    "cyan",
+    "blue",
+    "indigo",
+    "violet",
+    "magenta",
+    "pink",
+    "black",
+    "gray",
+    "white",
+    "dandelion",
+    "rose",
+    "brown_mushroom",
+    "red_mushroom",
+    "gold",
+    "iron",
+    "double_slab",
+    "slab",
+    "brick",
+    "tnt",
+    "bookshelf",
+    "mossy_rocks",
+    "obsidian",
+    "cobblestone_slab",
+    "rope",
+    "sandstone",
+    "snow",
+    "fire",
+    "light_pink",
+    "forest_green",
+    "brown",
+    "deep_blue",
+    "turquoise",
+    "ice",
+    "ceramic_tile",
+    "magma",
+    "pillar",
+    "crate",
+    "stone_brick",
+];
+
+/// Id of the default block with the given name, ignoring case
+pub fn from_name(name: &str) -> Option<u8> {
+    NAMES
+        .iter()
+        .position(|n| n.eq_ignore_ascii_case(name))
+        .map(|id| id as u8)
+}
+
+/// Whether a player can move through the block
+pub fn is_passable(block: u8) -> bool {
+    matches!(
+        block,
+        AIR | SAPLING
+            | WATER
+            | STILL_WATER
+            | LAVA
+            | STILL_LAVA
+            | DANDELION
+            | ROSE
+            | BROWN_MUSHROOM
+            | RED_MUSHROOM
+            | ROPE
+            | FIRE
+    )
+}
+
+/// Whether a player can swim or climb in the block
+pub fn is_climbable(block: u8) -> bool {
+    matches!(block, WATER | STILL_WATER | LAVA | STILL_LAVA | ROPE)
+}
+
+/// Whether the block is lower than a full block
+pub fn is_partial(block: u8) -> bool {
+    matches!(block, SLAB | COBBLESTONE_SLAB | SNOW)
+}
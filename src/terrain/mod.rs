@@ -17,13 +17,21 @@
 pub mod blocks;
 
 const CAVE_THRESHOLD: f64 = 0.3;
+/// Start of saved terrains, followed by the version of their format
+const MAGIC: &[u8; 4] = b"CLMP";
+/// Format written by [`Terrain::save`], saves without [`MAGIC`] are older
+const VERSION: u8 = 2;
 
 use std::io::Write;
 
+use bincode::Options;
 use classicl::{server::LevelDataChunk, Serialize};
-use flate2::{write::GzEncoder as Enc, Compression};
+use flate2::{
+    write::{DeflateEncoder, GzEncoder as Enc},
+    Compression,
+};
 use noise::{Abs, Add, Constant, Fbm, NoiseFn, ScaleBias, ScalePoint, SuperSimplex, Worley};
-use serde::Deserialize;
+use serde::{de::DeserializeOwned, Deserialize};
 
 use crate::PLAYER_HEIGHT;
 
@@ -121,7 +129,26 @@ impl TerrainNoise {
 #[derive(Serialize, Deserialize)]
 pub struct Terrain {
     pub size: (i16, i16, i16),
-    pub spawn_point: (i16, i16, i16),
+    pub spawn_point: (i32, i32, i32),
+    inner: Vec<u8>,
+    /// Upper bits of the block ids, empty as long as no block id exceeds 255
+    upper: Vec<u8>,
+}
+
+/// Terrain as saved before positions beyond 1023 blocks were supported
+#[derive(Deserialize)]
+struct TerrainV1 {
+    size: (i16, i16, i16),
+    spawn_point: (i16, i16, i16),
+    inner: Vec<u8>,
+    upper: Vec<u8>,
+}
+
+/// Terrain as saved before block ids above 255 were supported
+#[derive(Deserialize)]
+struct TerrainV0 {
+    size: (i16, i16, i16),
+    spawn_point: (i16, i16, i16),
     inner: Vec<u8>,
 }
 
@@ -135,9 +162,55 @@ impl Terrain {
                 to_fixed_point(10.0),
             ),
             inner: Self::generate(size, height, water_height),
+            upper: vec![],
         }
     }
 
+    /// Serializes the terrain for saving it, tagged with the format version.
+    pub fn save(&self) -> bincode::Result<Vec<u8>> {
+        let mut data = MAGIC.to_vec();
+        data.push(VERSION);
+        bincode::serialize_into(&mut data, self)?;
+        Ok(data)
+    }
+
+    /// Reads a saved terrain, including ones saved in older formats.
+    pub fn load(data: &[u8]) -> bincode::Result<Self> {
+        let invalid = |message: &str| Box::new(bincode::ErrorKind::Custom(message.into()));
+        match data.strip_prefix(MAGIC) {
+            Some([VERSION, data @ ..]) => Some(decode::<Self>(data)?)
+                .filter(Self::is_complete)
+                .ok_or_else(|| invalid("the blocks do not match the size of the map")),
+            Some(_) => Err(invalid("unknown version of the map format")),
+            None => Self::load_untagged(data).ok_or_else(|| invalid("unknown map format")),
+        }
+    }
+
+    /// Guesses the layout of saves from before the format was tagged with a version.
+    ///
+    /// Another layout can decode without errors, so only a layout whose blocks fit its size is
+    /// accepted.
+    fn load_untagged(data: &[u8]) -> Option<Self> {
+        decode::<Self>(data)
+            .ok()
+            .filter(Self::is_complete)
+            .or_else(|| decode::<TerrainV1>(data).ok().map(Self::from))
+            .filter(Self::is_complete)
+            .or_else(|| decode::<TerrainV0>(data).ok().map(Self::from))
+            .filter(Self::is_complete)
+    }
+
+    /// Whether there is exactly one block for every position
+    fn is_complete(&self) -> bool {
+        let (x, y, z) = self.size;
+        let volume = [x, y, z]
+            .into_iter()
+            .try_fold(1usize, |v, s| v.checked_mul(usize::try_from(s).ok()?));
+        volume.is_some_and(|v| {
+            self.inner.len() == v && (self.upper.is_empty() || self.upper.len() == v)
+        })
+    }
+
     fn generate(size: (i16, i16, i16), height: f64, water_height: i16) -> Vec<u8> {
         let mut tree_pos = vec![];
         let noise = TerrainNoise::new(height);
@@ -251,38 +324,135 @@ impl Terrain {
         buf
     }
 
-    pub fn to_chunks(&self) -> Vec<LevelDataChunk> {
-        let mut e = Enc::new(Vec::new(), Compression::fast());
-        let size: [u8; 4] =
-            (self.size.0 as u32 * self.size.1 as u32 * self.size.2 as u32).to_be_bytes();
-        e.write_all(&size).unwrap();
-        e.write_all(&self.inner).unwrap();
-        let data = e.finish().unwrap();
-        let mut bytes_sent = 0;
-        let total = data.len();
-        data.chunks(1024)
-            .map(|x| {
-                bytes_sent += x.len();
-                let percent_complete = ((bytes_sent as f32 / total as f32) * 100.0).floor() as u8;
-                LevelDataChunk {
-                    chunk_length: x.len() as i16,
-                    chunk_data: Vec::from(x),
-                    percent_complete,
-                }
-            })
-            .collect()
+    /// Compresses the level for sending it to a client.
+    ///
+    /// With FastMap the level is deflated without the length prefix. With ExtendedBlocks the
+    /// upper bits of the block ids follow as a second array, otherwise blocks above 255 are
+    /// replaced by [`blocks::FALLBACK`].
+    pub fn to_chunks(&self, fast_map: bool, extended_blocks: bool) -> Vec<LevelDataChunk> {
+        let upper = extended_blocks && !self.upper.is_empty();
+        let lower = if upper || self.upper.is_empty() {
+            compress(&self.inner, fast_map)
+        } else {
+            let blocks: Vec<u8> = self
+                .inner
+                .iter()
+                .zip(&self.upper)
+                .map(|(&b, &u)| if u == 0 { b } else { blocks::FALLBACK })
+                .collect();
+            compress(&blocks, fast_map)
+        };
+
+        // ExtendedBlocks uses the progress to tell which array a chunk belongs to
+        let mut chunks = to_chunks(&lower, if extended_blocks { Some(0) } else { None });
+        if upper {
+            chunks.append(&mut to_chunks(&compress(&self.upper, fast_map), Some(1)));
+        }
+        chunks
+    }
+
+    /// Number of blocks in the level
+    pub fn volume(&self) -> usize {
+        self.inner.len()
     }
 
-    pub fn set_block(&mut self, x: i16, y: i16, z: i16, t: u8) {
+    /// Position of a block in the block array, `None` if it is outside of the level
+    pub fn index_of(&self, x: i16, y: i16, z: i16) -> Option<usize> {
         let (x_size, _, z_size) = self.size;
-        if let Some(index) = index(x_size, z_size, x, y, z) {
-            if let Some(v) = self.inner.get_mut(index) {
-                *v = t;
+        if x < 0 || x >= x_size || z < 0 || z >= z_size {
+            return None;
+        }
+        index(x_size, z_size, x, y, z).filter(|&i| i < self.inner.len())
+    }
+
+    pub fn get_block(&self, x: i16, y: i16, z: i16) -> Option<u16> {
+        let index = self.index_of(x, y, z)?;
+        let lower = *self.inner.get(index)?;
+        let upper = self.upper.get(index).copied().unwrap_or(0);
+        Some(u16::from_be_bytes([upper, lower]))
+    }
+
+    pub fn set_block(&mut self, x: i16, y: i16, z: i16, t: u16) {
+        if let Some(index) = self.index_of(x, y, z) {
+            let [upper, lower] = t.to_be_bytes();
+            self.inner[index] = lower;
+            if upper != 0 && self.upper.is_empty() {
+                self.upper = vec![0; self.inner.len()];
+            }
+            if let Some(v) = self.upper.get_mut(index) {
+                *v = upper;
             }
         }
     }
 }
 
+impl From<TerrainV1> for Terrain {
+    fn from(old: TerrainV1) -> Self {
+        Self {
+            size: old.size,
+            spawn_point: widen(old.spawn_point),
+            inner: old.inner,
+            upper: old.upper,
+        }
+    }
+}
+
+impl From<TerrainV0> for Terrain {
+    fn from(old: TerrainV0) -> Self {
+        Self {
+            size: old.size,
+            spawn_point: widen(old.spawn_point),
+            inner: old.inner,
+            upper: vec![],
+        }
+    }
+}
+
+/// Decodes exactly one value in the layout written by [`bincode::serialize`], without trailing
+/// bytes.
+fn decode<T: DeserializeOwned>(data: &[u8]) -> bincode::Result<T> {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .reject_trailing_bytes()
+        .deserialize(data)
+}
+
+fn widen((x, y, z): (i16, i16, i16)) -> (i32, i32, i32) {
+    (x.into(), y.into(), z.into())
+}
+
+fn compress(blocks: &[u8], fast_map: bool) -> Vec<u8> {
+    if fast_map {
+        let mut e = DeflateEncoder::new(Vec::new(), Compression::fast());
+        e.write_all(blocks).unwrap();
+        e.finish().unwrap()
+    } else {
+        let mut e = Enc::new(Vec::new(), Compression::fast());
+        let size: [u8; 4] = (blocks.len() as u32).to_be_bytes();
+        e.write_all(&size).unwrap();
+        e.write_all(blocks).unwrap();
+        e.finish().unwrap()
+    }
+}
+
+/// Splits compressed level data into chunks, `array` replaces the progress if given.
+fn to_chunks(data: &[u8], array: Option<u8>) -> Vec<LevelDataChunk> {
+    let mut bytes_sent = 0;
+    let total = data.len();
+    data.chunks(1024)
+        .map(|x| {
+            bytes_sent += x.len();
+            let percent_complete =
+                array.unwrap_or_else(|| ((bytes_sent as f32 / total as f32) * 100.0).floor() as u8);
+            LevelDataChunk {
+                chunk_length: x.len() as i16,
+                chunk_data: Vec::from(x),
+                percent_complete,
+            }
+        })
+        .collect()
+}
+
 fn index(x_size: i16, z_size: i16, x: i16, y: i16, z: i16) -> Option<usize> {
     let index = x as i64 + x_size as i64 * (z as i64 + z_size as i64 * y as i64);
 
@@ -304,3 +474,83 @@ pub struct Decoration {
     z: i16,
     t: DecorationType,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: (i16, i16, i16) = (4, 2, 4);
+
+    /// Air at the start, like the sky above a flat map
+    fn blocks() -> Vec<u8> {
+        let mut blocks = vec![blocks::AIR; 32];
+        blocks[20] = blocks::STONE;
+        blocks
+    }
+
+    #[test]
+    fn saves_load_again() {
+        let terrain = Terrain {
+            size: SIZE,
+            spawn_point: (2000, 100, 3000),
+            inner: blocks(),
+            upper: vec![],
+        };
+        let loaded = Terrain::load(&terrain.save().unwrap()).unwrap();
+        assert_eq!(loaded.size, SIZE);
+        assert_eq!(loaded.spawn_point, (2000, 100, 3000));
+        assert_eq!(loaded.inner, blocks());
+    }
+
+    #[test]
+    fn v0_saves_starting_with_air_load() {
+        let data = bincode::serialize(&(SIZE, (64i16, 80i16, 64i16), blocks())).unwrap();
+        let loaded = Terrain::load(&data).unwrap();
+        assert_eq!(loaded.size, SIZE);
+        assert_eq!(loaded.spawn_point, (64, 80, 64));
+        assert_eq!(loaded.inner, blocks());
+        assert!(loaded.upper.is_empty());
+    }
+
+    #[test]
+    fn v1_saves_starting_with_air_load() {
+        let upper = vec![0; 32];
+        let data =
+            bincode::serialize(&(SIZE, (64i16, 80i16, 64i16), blocks(), upper.clone())).unwrap();
+        let loaded = Terrain::load(&data).unwrap();
+        assert_eq!(loaded.spawn_point, (64, 80, 64));
+        assert_eq!(loaded.inner, blocks());
+        assert_eq!(loaded.upper, upper);
+    }
+
+    #[test]
+    fn upper_array_is_sent_without_fast_map() {
+        let mut upper = vec![0; 32];
+        upper[20] = 1;
+        let terrain = Terrain {
+            size: SIZE,
+            spawn_point: (0, 0, 0),
+            inner: blocks(),
+            upper,
+        };
+        for fast_map in [false, true] {
+            let chunks = terrain.to_chunks(fast_map, true);
+            assert_eq!(chunks[0].percent_complete, 0);
+            assert_eq!(chunks.last().unwrap().percent_complete, 1);
+        }
+        let chunks = terrain.to_chunks(false, false);
+        assert!(chunks.iter().all(|c| c.percent_complete == 100));
+    }
+
+    #[test]
+    fn incomplete_maps_are_rejected() {
+        let data = bincode::serialize(&(SIZE, (64i16, 80i16, 64i16), vec![0u8; 31])).unwrap();
+        assert!(Terrain::load(&data).is_err());
+        let mut data = MAGIC.to_vec();
+        data.push(VERSION);
+        data.extend(
+            bincode::serialize(&(SIZE, (0, 0, 0), vec![0u8; 31], Vec::<u8>::new())).unwrap(),
+        );
+        assert!(Terrain::load(&data).is_err());
+    }
+}
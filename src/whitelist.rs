@@ -0,0 +1,97 @@
+/* This file is part of classicl-server.
+ *
+ * classicl-server is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Location of the whitelist inside the data directory
+pub fn path(data: &Path) -> PathBuf {
+    data.join("whitelist.toml")
+}
+
+/// Names allowed to join while the whitelist is enabled, saved in `whitelist.toml` inside the data
+/// directory
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Whitelist {
+    #[serde(skip)]
+    path: PathBuf,
+    pub enabled: bool,
+    /// Shown to players who are not on the whitelist
+    pub message: String,
+    /// Lowercase player names
+    players: BTreeSet<String>,
+}
+
+impl Default for Whitelist {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::new(),
+            enabled: false,
+            message: "You are not whitelisted on this server".into(),
+            players: BTreeSet::new(),
+        }
+    }
+}
+
+impl Whitelist {
+    pub fn load(path: &Path) -> Self {
+        let mut whitelist: Self = if let Ok(s) = fs::read_to_string(path) {
+            toml::from_str(&s).unwrap()
+        } else {
+            Self::default()
+        };
+        whitelist.path = path.to_path_buf();
+        whitelist
+    }
+
+    fn save(&self) {
+        fs::write(&self.path, toml::to_string_pretty(self).unwrap()).unwrap();
+    }
+
+    /// Whether `name` may join, which everyone may while the whitelist is disabled
+    pub fn allows(&self, name: &str) -> bool {
+        !self.enabled || self.players.contains(&name.to_lowercase())
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.save();
+    }
+
+    /// Adds a name, returns whether it was not on the whitelist before.
+    pub fn add(&mut self, name: &str) -> bool {
+        let added = self.players.insert(name.to_lowercase());
+        self.save();
+        added
+    }
+
+    /// Removes a name, returns whether it was on the whitelist.
+    pub fn remove(&mut self, name: &str) -> bool {
+        let removed = self.players.remove(&name.to_lowercase());
+        self.save();
+        removed
+    }
+
+    pub fn players(&self) -> impl Iterator<Item = &String> {
+        self.players.iter()
+    }
+}
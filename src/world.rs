@@ -0,0 +1,99 @@
+/* This file is part of classicl-server.
+ *
+ * classicl-server is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use classicl::server::SetTextHotKey;
+use serde::{Deserialize, Serialize};
+
+use crate::{hacks::Hacks, ranks::Rank};
+
+/// Location of the world settings inside the data directory
+pub fn path(data: &Path) -> PathBuf {
+    data.join("world.toml")
+}
+
+/// Settings of the world, editable in `world.toml` inside the data directory
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorldSettings {
+    /// Replaces the server name shown while joining
+    pub name: Option<String>,
+    /// Replaces the server MOTD shown while joining
+    pub motd: Option<String>,
+    /// Lowest rank allowed to join
+    pub visit_rank: Rank,
+    /// Lowest rank allowed to change blocks
+    pub build_rank: Rank,
+    pub hacks: Hacks,
+    /// Replaces the allowed hacks for players of a rank
+    pub rank_hacks: BTreeMap<Rank, Hacks>,
+    pub hotkeys: Vec<HotKey>,
+}
+
+/// Key press which makes the client send a chat message
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HotKey {
+    pub label: String,
+    /// Message to send, usually a command
+    pub action: String,
+    /// LWJGL key code
+    pub key: i32,
+    #[serde(default)]
+    pub ctrl: bool,
+    #[serde(default)]
+    pub shift: bool,
+    #[serde(default)]
+    pub alt: bool,
+}
+
+impl HotKey {
+    pub fn to_set_text_hot_key(&self) -> SetTextHotKey {
+        SetTextHotKey {
+            label: self.label.clone(),
+            // The newline makes the client send the message right away
+            action: format!("{}\n", self.action),
+            key_code: self.key,
+            key_mods: u8::from(self.ctrl) | u8::from(self.shift) << 1 | u8::from(self.alt) << 2,
+        }
+    }
+}
+
+impl WorldSettings {
+    /// Loads the settings from `path` and writes the defaults there if it does not exist yet.
+    pub fn load(path: &Path) -> Self {
+        if let Ok(s) = fs::read_to_string(path) {
+            toml::from_str(&s).unwrap()
+        } else {
+            let settings = Self::default();
+            settings.save(path);
+            settings
+        }
+    }
+
+    /// Hacks allowed for players of the given rank
+    pub fn hacks_for(&self, rank: Rank) -> Hacks {
+        self.rank_hacks.get(&rank).copied().unwrap_or(self.hacks)
+    }
+
+    pub fn save(&self, path: &Path) {
+        fs::write(path, toml::to_string_pretty(self).unwrap()).unwrap();
+    }
+}